@@ -0,0 +1,160 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::manifest::TraceManifest;
+
+/// The checked-out state of the git repository a trace was recorded in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct GitHead {
+    /// `None` on a detached HEAD
+    pub(super) branch: Option<String>,
+    pub(super) commit: String,
+}
+
+/// Trimmed stdout of `git ARGS` run in `dir`, if it succeeds.
+pub(super) fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let out = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .inspect_err(|e| log::debug!("git {args:?} failed: {e}"))
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// The current branch and commit of `dir`, when it is inside a git work tree.
+pub(super) fn head(dir: &Path) -> Option<GitHead> {
+    let commit = git(dir, &["rev-parse", "HEAD"])?;
+    let branch = git(dir, &["symbolic-ref", "--quiet", "--short", "HEAD"]);
+    Some(GitHead { branch, commit })
+}
+
+/// Names of the local branches of the repository containing `dir`.
+pub(super) fn local_branches(dir: &Path) -> Option<HashSet<String>> {
+    let list = git(
+        dir,
+        &["for-each-ref", "--format=%(refname:short)", "refs/heads"],
+    )?;
+    Some(list.lines().map(str::to_string).collect())
+}
+
+/// Which stored traces protect their artifacts: every trace of the `recent`
+/// most recently traced branches besides `current`, and of the `named` ones.
+/// Only branches that still exist locally count.
+pub(super) fn protecting<'a>(
+    manifests: &'a [TraceManifest],
+    current: Option<&str>,
+    recent: usize,
+    named: &[String],
+    existing: &HashSet<String>,
+) -> Vec<&'a TraceManifest> {
+    let mut by_recency: Vec<&TraceManifest> = manifests
+        .iter()
+        .filter(|m| {
+            m.branch
+                .as_deref()
+                .is_some_and(|b| existing.contains(b) && Some(b) != current)
+        })
+        .collect();
+    by_recency.sort_by_key(|m| std::cmp::Reverse(m.recorded_at));
+    let mut branches: BTreeSet<&str> = named.iter().map(String::as_str).collect();
+    let mut seen = HashSet::new();
+    for manifest in &by_recency {
+        let branch = manifest.branch.as_deref().unwrap_or_default();
+        if seen.len() < recent && seen.insert(branch) {
+            branches.insert(branch);
+        }
+    }
+    manifests
+        .iter()
+        .filter(|m| {
+            m.branch
+                .as_deref()
+                .is_some_and(|b| branches.contains(b) && existing.contains(b))
+        })
+        .collect()
+}
+
+/// Add the artifacts of `manifests` that still exist to `used`, printing the
+/// branches they were traced on.
+pub(super) fn protect(manifests: &[&TraceManifest], used: &mut HashSet<PathBuf>) {
+    let branches: BTreeSet<&str> = manifests
+        .iter()
+        .filter_map(|m| m.branch.as_deref())
+        .collect();
+    if branches.is_empty() {
+        return;
+    }
+    let before = used.len();
+    used.extend(
+        manifests
+            .iter()
+            .flat_map(|m| &m.artifacts)
+            .filter(|a| a.exists())
+            .cloned(),
+    );
+    let branches: Vec<&str> = branches.into_iter().collect();
+    println!(
+        "🌿 Protecting {} more artifacts traced on \x1b[1;36m{}\x1b[0m",
+        used.len() - before,
+        branches.join("\x1b[0m, \x1b[1;36m")
+    );
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(command: &str, branch: Option<&str>, recorded_at: u64) -> TraceManifest {
+        TraceManifest {
+            command: command.to_string(),
+            recorded_at,
+            artifacts: Vec::new(),
+            branch: branch.map(str::to_string),
+            commit: None,
+        }
+    }
+
+    #[test]
+    fn recent_and_named_existing_branches_are_protected() {
+        let manifests = [
+            trace("cargo build", Some("main"), 50),
+            trace("cargo build", Some("feature"), 40),
+            trace("cargo test", Some("feature"), 10),
+            trace("cargo build", Some("release-1.x"), 30),
+            trace("cargo build", Some("old"), 20),
+            trace("cargo build", Some("deleted"), 45),
+            trace("cargo build", None, 60),
+        ];
+        let existing: HashSet<String> = ["main", "feature", "release-1.x", "old"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let picked = |current, recent, named: &[String]| -> Vec<(String, String)> {
+            protecting(&manifests, current, recent, named, &existing)
+                .into_iter()
+                .map(|m| (m.branch.clone().unwrap(), m.command.clone()))
+                .collect()
+        };
+
+        // `deleted` is newer but gone; `main` is checked out and traced anyway
+        assert_eq!(
+            picked(Some("main"), 1, &[]),
+            [
+                ("feature".to_string(), "cargo build".to_string()),
+                ("feature".to_string(), "cargo test".to_string())
+            ]
+        );
+        assert_eq!(
+            picked(Some("main"), 0, &["old".to_string(), "deleted".to_string()]),
+            [("old".to_string(), "cargo build".to_string())]
+        );
+        assert_eq!(picked(Some("main"), 2, &[]).len(), 3);
+        assert!(picked(None, 0, &[]).is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use cargo_metadata::{Metadata, MetadataCommand};
+use serde::Deserialize;
+
+use super::cold::discover_profile_dirs;
+use super::scan::{SizeCache, SizeMode};
+use super::stats::{CleanupStats, DirToRemove, RemovalKind};
+use super::units::unit_kind;
+
+/// The field `cargo metadata` reports since cargo 1.91, which
+/// `cargo_metadata` does not know yet.
+#[derive(Debug, Deserialize)]
+struct BuildDirectory {
+    build_directory: Option<PathBuf>,
+}
+
+/// `cargo metadata` of the workspace at `dir` (or of `manifest_path`), and
+/// where cargo puts its intermediate artifacts (the target directory unless a
+/// build-dir is set).
+pub(super) fn metadata(dir: &Path, manifest_path: Option<&Path>) -> Result<(Metadata, PathBuf)> {
+    let mut cmd = MetadataCommand::new();
+    cmd.current_dir(dir);
+    if let Some(manifest_path) = manifest_path {
+        cmd.manifest_path(manifest_path);
+    }
+    let output = cmd
+        .cargo_command()
+        .stderr(Stdio::piped())
+        .output()
+        .context("failed to run cargo metadata")?;
+    if !output.status.success() {
+        bail!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8(output.stdout).context("cargo metadata output")?;
+    let json = stdout
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .context("no JSON in the output of cargo metadata")?;
+    let metadata = MetadataCommand::parse(json)?;
+    let reported = serde_json::from_str::<BuildDirectory>(json)?.build_directory;
+    let build_dir = reported
+        .or_else(|| configured(dir, metadata.workspace_root.as_std_path()))
+        .unwrap_or_else(|| metadata.target_directory.clone().into_std_path_buf());
+    Ok((metadata, build_dir))
+}
+
+/// The build-dir set by `CARGO_BUILD_BUILD_DIR` or `build.build-dir` in the
+/// cargo config files that apply to `dir`, for cargo that predates reporting
+/// it in metadata.
+fn configured(dir: &Path, workspace_root: &Path) -> Option<PathBuf> {
+    if let Some(value) = std::env::var_os("CARGO_BUILD_BUILD_DIR") {
+        return expand(&value.to_string_lossy(), workspace_root).map(|p| dir.join(p));
+    }
+    // The config closest to `dir` wins, then the one in the cargo home
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::home_dir().map(|h| h.join(".cargo")));
+    let config_dirs = dir.ancestors().map(|d| d.join(".cargo")).chain(cargo_home);
+    for config_dir in config_dirs {
+        for name in ["config.toml", "config"] {
+            let Ok(text) = std::fs::read_to_string(config_dir.join(name)) else {
+                continue;
+            };
+            if let Some(value) = build_dir_setting(&text) {
+                // Relative to the directory holding `.cargo/`
+                let base = config_dir.parent().unwrap_or(&config_dir);
+                return expand(&value, workspace_root).map(|p| base.join(p));
+            }
+        }
+    }
+    None
+}
+
+/// `build.build-dir` of a cargo config file.
+fn build_dir_setting(config: &str) -> Option<String> {
+    let config = config.parse::<toml::Table>().ok()?;
+    let value = config.get("build")?.get("build-dir")?.as_str()?;
+    Some(value.to_string())
+}
+
+/// Fill in the `{workspace-root}` and `{cargo-cache-home}` templates of a
+/// build-dir. `{workspace-path-hash}` can't be reproduced outside cargo.
+fn expand(template: &str, workspace_root: &Path) -> Option<PathBuf> {
+    if template.contains("{workspace-path-hash}") {
+        log::warn!("build-dir {template} uses {{workspace-path-hash}}; upgrade cargo to 1.91+");
+        return None;
+    }
+    let mut expanded = template.replace("{workspace-root}", &workspace_root.to_string_lossy());
+    if expanded.contains("{cargo-cache-home}") {
+        let home = std::env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::home_dir().map(|h| h.join(".cargo")))?;
+        expanded = expanded.replace("{cargo-cache-home}", &home.to_string_lossy());
+    }
+    Some(PathBuf::from(expanded))
+}
+
+/// Unit directories of the per-unit layout (`-Zbuild-dir-new-layout`): each
+/// unit gets `<profile>/build/<package>/<hash>/` with its `fingerprint/` and
+/// its artifacts in `out/`, instead of sharing `deps/` and `.fingerprint/`.
+pub(super) fn unit_dirs(profile_dir: &Path) -> Vec<PathBuf> {
+    let children = |dir: &Path| -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect()
+    };
+    let mut units: Vec<PathBuf> = children(&profile_dir.join("build"))
+        .iter()
+        .flat_map(|package| children(package))
+        .filter(|unit| unit.join("fingerprint").is_dir())
+        .collect();
+    units.sort();
+    units
+}
+
+/// Every directory of `root` holding artifacts next to their dep-info: the
+/// `deps/` of each profile, or the `out/` of each unit in the per-unit layout.
+pub(super) fn artifact_dirs(root: &Path) -> Vec<(PathBuf, String)> {
+    let mut dirs = Vec::new();
+    for (profile_dir, profile) in discover_profile_dirs(root) {
+        if has_unit_layout(&profile_dir) {
+            dirs.extend(
+                unit_dirs(&profile_dir)
+                    .into_iter()
+                    .map(|unit| (unit.join("out"), profile.clone())),
+            );
+        } else if profile_dir.join("deps").is_dir() {
+            dirs.push((profile_dir.join("deps"), profile));
+        }
+    }
+    dirs
+}
+
+/// Whether `profile_dir` uses the per-unit layout.
+pub(super) fn has_unit_layout(profile_dir: &Path) -> bool {
+    !profile_dir.join("deps").is_dir() && !unit_dirs(profile_dir).is_empty()
+}
+
+/// Whether the unit compiles or runs a build script; those are left alone,
+/// as `build/` is in the classic layout.
+fn is_build_script(unit_dir: &Path) -> bool {
+    std::fs::read_dir(unit_dir.join("fingerprint"))
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| unit_kind(e.path().file_stem()?.to_str()?))
+        .any(|kind| kind.ends_with("build-script"))
+}
+
+/// The package a unit dir belongs to, as a crate key.
+fn unit_package(unit_dir: &Path) -> String {
+    unit_dir
+        .parent()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().replace('-', "_"))
+        .unwrap_or_default()
+}
+
+/// When the unit last produced something: the newest file of its `out/`.
+fn unit_mtime(unit_dir: &Path) -> SystemTime {
+    std::fs::read_dir(unit_dir.join("out"))
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok()?.metadata().ok()?.modified().ok())
+        .max()
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// The `keep_last` most recently built units of every package, the per-unit
+/// counterpart of `scan::recent_stems`.
+fn recent_units(units: &[PathBuf], keep_last: usize) -> HashSet<PathBuf> {
+    let mut builds: HashMap<String, Vec<(SystemTime, &PathBuf)>> = HashMap::new();
+    for unit_dir in units {
+        builds
+            .entry(unit_package(unit_dir))
+            .or_default()
+            .push((unit_mtime(unit_dir), unit_dir));
+    }
+    builds
+        .into_values()
+        .flat_map(|mut units| {
+            units.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+            units
+                .into_iter()
+                .take(keep_last)
+                .map(|(_, dir)| dir.clone())
+        })
+        .collect()
+}
+
+/// Mark the unit dirs of a per-unit layout profile for removal when none of
+/// their artifacts was used, they don't build one of `outputs` and they are
+/// not among the `keep_last` newest of their package.
+pub(super) fn clean_unit_dirs(
+    profile_dir: &Path,
+    used_artifacts: &HashSet<PathBuf>,
+    outputs: &HashSet<String>,
+    profile: &str,
+    sizes: &SizeCache,
+    mode: SizeMode,
+    keep_last: usize,
+) -> CleanupStats {
+    let mut stats = CleanupStats::default();
+    let units: Vec<PathBuf> = unit_dirs(profile_dir)
+        .into_iter()
+        .filter(|unit_dir| !is_build_script(unit_dir))
+        .collect();
+    let recent = recent_units(&units, keep_last);
+    for unit_dir in units {
+        let package = unit_package(&unit_dir);
+        let dir_sizes = sizes.size_of(&unit_dir);
+        let size = dir_sizes.get(mode);
+        if used_artifacts.iter().any(|a| a.starts_with(&unit_dir)) || outputs.contains(&package) {
+            stats.used_bytes += size;
+            stats
+                .per_profile
+                .entry(profile.to_string())
+                .or_default()
+                .used_bytes += size;
+            continue;
+        }
+        if recent.contains(&unit_dir) {
+            stats.add_retained(profile, size);
+            stats.retained_builds += 1;
+            continue;
+        }
+        stats.add_reclaimable(profile, size, dir_sizes);
+        let crate_entry = stats.per_crate.entry(package).or_default();
+        crate_entry.files += 1;
+        crate_entry.bytes += size;
+        stats.dirs_to_remove.push(DirToRemove {
+            path: unit_dir,
+            size,
+            sizes: dir_sizes,
+            profile: profile.to_string(),
+            kind: RemovalKind::Artifact,
+        });
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn build_dir_comes_from_config_templates() {
+        let config = "[build]\nbuild-dir = \"{workspace-root}/build-cache\"\n";
+        let value = build_dir_setting(config).unwrap();
+        assert_eq!(
+            expand(&value, Path::new("/ws")),
+            Some(PathBuf::from("/ws/build-cache"))
+        );
+        assert_eq!(build_dir_setting("[build]\ntarget-dir = \"t\"\n"), None);
+        assert_eq!(expand("{workspace-path-hash}/x", Path::new("/ws")), None);
+    }
+
+    #[test]
+    fn artifact_dirs_cover_both_layouts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("release/deps")).unwrap();
+        fs::create_dir_all(root.join("debug/build/l/aaaa/fingerprint")).unwrap();
+        fs::create_dir_all(root.join("debug/build/l/aaaa/out")).unwrap();
+        fs::write(root.join("debug/.cargo-build-lock"), "").unwrap();
+
+        assert_eq!(
+            artifact_dirs(root),
+            [
+                (root.join("debug/build/l/aaaa/out"), "debug".to_string()),
+                (root.join("release/deps"), "release".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_units_of_the_per_unit_layout_are_removed_whole() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile = tmp.path().join("debug");
+        for (unit, fingerprint, artifact) in [
+            ("l/aaaa", "lib-l.json", "libl-aaaa.rlib"),
+            ("l/bbbb", "lib-l.json", "libl-bbbb.rlib"),
+            ("app/cccc", "bin-app.json", "app"),
+            (
+                "l/dddd",
+                "build-script-build-script-build.json",
+                "build-script-build",
+            ),
+        ] {
+            let dir = profile.join("build").join(unit);
+            fs::create_dir_all(dir.join("fingerprint")).unwrap();
+            fs::create_dir_all(dir.join("out")).unwrap();
+            fs::write(dir.join("fingerprint").join(fingerprint), "{}").unwrap();
+            fs::write(dir.join("out").join(artifact), "x").unwrap();
+        }
+        assert!(has_unit_layout(&profile));
+
+        let used = HashSet::from([profile.join("build/l/aaaa/out/libl-aaaa.rlib")]);
+        let outputs = HashSet::from(["app".to_string()]);
+        let stats = clean_unit_dirs(
+            &profile,
+            &used,
+            &outputs,
+            "debug",
+            &SizeCache::default(),
+            SizeMode::Apparent,
+            0,
+        );
+        let removed: Vec<&Path> = stats
+            .dirs_to_remove
+            .iter()
+            .map(|d| d.path.as_path())
+            .collect();
+        assert_eq!(removed, [profile.join("build/l/bbbb")]);
+        assert_eq!(stats.per_crate.get("l").map(|c| c.files), Some(1));
+
+        // `--keep-last 1` retains the newest unit of `l`, the traced one
+        // aside; an older one still goes
+        fs::create_dir_all(profile.join("build/l/eeee/fingerprint")).unwrap();
+        fs::create_dir_all(profile.join("build/l/eeee/out")).unwrap();
+        fs::write(profile.join("build/l/eeee/fingerprint/lib-l.json"), "{}").unwrap();
+        let old = profile.join("build/l/eeee/out/libl-eeee.rlib");
+        fs::write(&old, "x").unwrap();
+        filetime::set_file_mtime(&old, filetime::FileTime::from_unix_time(1, 0)).unwrap();
+        let stats = clean_unit_dirs(
+            &profile,
+            &HashSet::new(),
+            &outputs,
+            "debug",
+            &SizeCache::default(),
+            SizeMode::Apparent,
+            1,
+        );
+        let removed: Vec<&Path> = stats
+            .dirs_to_remove
+            .iter()
+            .map(|d| d.path.as_path())
+            .collect();
+        assert_eq!(removed.len(), 2);
+        assert!(removed.contains(&profile.join("build/l/eeee").as_path()));
+        assert_eq!(stats.retained_builds, 1);
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+
+use crate::crate_deps::format_bytes;
+
+use super::stats::CleanupStats;
+
+/// Nothing reclaimable (or below `--fail-if-reclaimable`), or everything was removed.
+pub(super) const EXIT_CLEAN: i32 = 0;
+/// `-c` was not given and there is no terminal to pick a command from.
+pub(super) const EXIT_USAGE: i32 = 2;
+/// Reclaimable artifacts were found (dry-run) or exceeded `--fail-if-reclaimable`.
+pub(super) const EXIT_RECLAIMABLE: i32 = 3;
+/// Removal was attempted but some files or directories could not be removed.
+pub(super) const EXIT_REMOVAL_FAILED: i32 = 4;
+/// The traced build command could not be run or exited unsuccessfully.
+pub(super) const EXIT_TRACE_FAILED: i32 = 5;
+
+/// Error context marking a failed trace, so `--ci` can map it to
+/// [`EXIT_TRACE_FAILED`].
+#[derive(Debug)]
+pub(super) struct TraceFailed;
+
+impl fmt::Display for TraceFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Failed to trace build command")
+    }
+}
+
+/// The `--ci` exit code for a finished run.
+///
+/// `planned` is what was found, `removed` the outcome of removal when it ran
+/// (`-y`).  Without a threshold, anything left behind by a dry-run counts as
+/// reclaimable, even files whose blocks a surviving hard link keeps; with
+/// one, the planned total is compared against it even when it was removed,
+/// so a pipeline can assert its caches stay lean.
+pub(super) fn exit_code(
+    planned: &CleanupStats,
+    removed: Option<&CleanupStats>,
+    threshold: Option<u64>,
+) -> i32 {
+    if removed.is_some_and(|r| !r.errors.is_empty()) {
+        return EXIT_REMOVAL_FAILED;
+    }
+    let over_threshold = match threshold {
+        Some(limit) => planned.bytes > limit,
+        None => removed.is_none() && (planned.files > 0 || !planned.dirs_to_remove.is_empty()),
+    };
+    if over_threshold {
+        EXIT_RECLAIMABLE
+    } else {
+        EXIT_CLEAN
+    }
+}
+
+/// One line on stderr explaining a non-zero `--ci` exit.
+pub(super) fn explain(code: i32, planned: &CleanupStats, threshold: Option<u64>) -> String {
+    match (code, threshold) {
+        (EXIT_REMOVAL_FAILED, _) => "ci: some artifacts could not be removed".to_string(),
+        (EXIT_RECLAIMABLE, Some(limit)) => format!(
+            "ci: {} reclaimable exceeds --fail-if-reclaimable {}",
+            format_bytes(planned.bytes),
+            format_bytes(limit)
+        ),
+        (EXIT_RECLAIMABLE, None) => format!(
+            "ci: {} files ({}) reclaimable (re-run with -y to remove)",
+            planned.files,
+            format_bytes(planned.bytes)
+        ),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planned(bytes: u64) -> CleanupStats {
+        CleanupStats {
+            files: usize::from(bytes > 0),
+            bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dry_run_fails_on_any_garbage_without_threshold() {
+        assert_eq!(exit_code(&planned(0), None, None), EXIT_CLEAN);
+        assert_eq!(exit_code(&planned(1), None, None), EXIT_RECLAIMABLE);
+        // Hard-linked elsewhere: no blocks freed, but still garbage
+        let linked = CleanupStats {
+            files: 2,
+            ..Default::default()
+        };
+        assert_eq!(exit_code(&linked, None, None), EXIT_RECLAIMABLE);
+        assert_eq!(exit_code(&linked, None, Some(0)), EXIT_CLEAN);
+    }
+
+    #[test]
+    fn removal_succeeds_unless_threshold_exceeded() {
+        let removed = CleanupStats::default();
+        assert_eq!(exit_code(&planned(10), Some(&removed), None), EXIT_CLEAN);
+        assert_eq!(
+            exit_code(&planned(10), Some(&removed), Some(10)),
+            EXIT_CLEAN
+        );
+        assert_eq!(
+            exit_code(&planned(11), Some(&removed), Some(10)),
+            EXIT_RECLAIMABLE
+        );
+    }
+
+    #[test]
+    fn removal_errors_take_precedence() {
+        let mut removed = CleanupStats::default();
+        removed.errors.insert(
+            ("x".to_string(), "debug".to_string(), "x-1.rlib".to_string()),
+            anyhow::anyhow!("permission denied"),
+        );
+        assert_eq!(
+            exit_code(&planned(11), Some(&removed), Some(10)),
+            EXIT_REMOVAL_FAILED
+        );
+    }
+
+    #[test]
+    fn trace_failure_is_found_through_context() {
+        let err = anyhow::anyhow!("exit status 101")
+            .context(TraceFailed)
+            .context("failed to clean up unused files");
+        assert!(err.downcast_ref::<TraceFailed>().is_some());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use cargo_metadata::MetadataCommand;
+use clap::{ArgAction, Args, ValueHint};
+
+use crate::crate_deps::format_bytes;
+use crate::trace_parser::{TraceParser, TraceResult};
+
+use super::CleanCommand;
+use super::ci::{EXIT_REMOVAL_FAILED, EXIT_TRACE_FAILED};
+use super::display::{print_build_profiles, print_profile_breakdown};
+use super::scan::{SizeCache, SizeMode, artifact_stem, output_crate_names, traced_deps_dirs};
+use super::stats::{CleanupStats, DirToRemove, RemovalKind};
+
+/// Prune a CI cache of `target/` before it is saved.
+///
+/// Traces the job's build/test commands (or reads recorded traces), removes
+/// every `deps/` artifact they did not use plus the whole `incremental/`
+/// directory of each traced profile, and appends a Markdown summary to the
+/// file named by `$GITHUB_STEP_SUMMARY`. Never prompts.
+#[derive(Debug, Clone, Args)]
+pub(crate) struct CiCacheCommand {
+    /// Build or test command the job ran; repeat for several
+    /// (e.g. -c "cargo build" -c "cargo test --no-run").
+    #[clap(
+        short = 'c',
+        long = "command",
+        value_name = "COMMAND",
+        required_unless_present = "trace_logs"
+    )]
+    commands: Vec<String>,
+
+    /// Reuse a recorded trace instead of running a command: the stderr of a
+    /// build run with CARGO_LOG=cargo::core::compiler::fingerprint=trace.
+    #[clap(long = "trace-log", value_name = "FILE", value_hint = ValueHint::FilePath)]
+    trace_logs: Vec<PathBuf>,
+
+    /// Also remove the `.fingerprint/` entries of removed units.
+    #[clap(long, action = ArgAction::SetTrue)]
+    fingerprints: bool,
+
+    /// Show what would be removed without removing anything.
+    #[clap(long, action = ArgAction::SetTrue)]
+    dry_run: bool,
+
+    /// Target directory to prune (defaults to the one reported by
+    /// `cargo metadata --offline --no-deps`).
+    #[clap(long, value_hint = ValueHint::DirPath, value_name = "PATH")]
+    target_dir: Option<PathBuf>,
+
+    /// Environment variable naming the file the Markdown summary is appended to.
+    #[clap(long, default_value = "GITHUB_STEP_SUMMARY", value_name = "VAR")]
+    summary_env: String,
+
+    /// Measure sizes by logical file length instead of allocated disk blocks.
+    #[clap(long, action = ArgAction::SetTrue)]
+    apparent_size: bool,
+
+    /// Workspace directory; traced commands run here.
+    #[clap(
+        value_hint = ValueHint::DirPath,
+        default_value = ".",
+        value_name = "DIR"
+    )]
+    dir: PathBuf,
+}
+
+impl CiCacheCommand {
+    fn size_mode(&self) -> SizeMode {
+        if self.apparent_size {
+            SizeMode::Apparent
+        } else {
+            SizeMode::DiskUsage
+        }
+    }
+
+    fn resolve_target_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = &self.target_dir {
+            // Traced artifact paths are absolute
+            return std::path::absolute(dir)
+                .with_context(|| format!("resolving {}", dir.display()));
+        }
+        let metadata = MetadataCommand::new()
+            .current_dir(&self.dir)
+            .no_deps()
+            .other_options(vec!["--offline".to_string()])
+            .exec()
+            .context("failed to read cargo metadata (pass --target-dir to skip it)")?;
+        Ok(metadata.target_directory.into_std_path_buf())
+    }
+
+    async fn collect_traces(&self, target_dir: &Path) -> Result<TraceResult> {
+        let parser = TraceParser::new(target_dir.to_path_buf());
+        let mut trace = TraceResult::default();
+        for cmd in &self.commands {
+            match parser.trace(&self.dir, cmd).await {
+                Ok(result) => trace.merge_from(result),
+                Err(e) => {
+                    eprintln!("\x1b[1;31merror\x1b[0m: Failed to trace build command: {e:#}");
+                    std::process::exit(EXIT_TRACE_FAILED);
+                }
+            }
+        }
+        for log in &self.trace_logs {
+            trace.merge_from(parser.parse_log(log)?);
+        }
+        Ok(trace)
+    }
+
+    /// Plan the removal for one traced `deps/` directory.
+    async fn plan_profile(
+        &self,
+        deps_dir: &Path,
+        profile: &str,
+        trace: &TraceResult,
+        sizes: &SizeCache,
+    ) -> Result<CleanupStats> {
+        let mode = self.size_mode();
+        let outputs = deps_dir
+            .parent()
+            .map(output_crate_names)
+            .unwrap_or_default();
+        let mut stats = CleanCommand::clean_with_trace_result(
+            deps_dir,
+            &trace.used_artifacts,
+            &outputs,
+            profile,
+            sizes,
+            mode,
+            0,
+        )
+        .await
+        .with_context(|| format!("Failed to clean profile: {profile}"))?;
+
+        let Some(profile_dir) = deps_dir.parent() else {
+            return Ok(stats);
+        };
+        stats
+            .per_profile
+            .entry(profile.to_string())
+            .or_default()
+            .total_dir_bytes = sizes.size_of(profile_dir).get(mode);
+
+        // A CI job starts from a fresh checkout, so no session is ever reused
+        let incremental = profile_dir.join("incremental");
+        if incremental.is_dir() {
+            push_dir(
+                &mut stats,
+                sizes,
+                mode,
+                incremental,
+                profile,
+                RemovalKind::Incremental,
+            );
+        }
+
+        if self.fingerprints {
+            let removed_hashes: HashSet<String> = stats
+                .files_to_remove
+                .iter()
+                .filter_map(|f| unit_hash(&artifact_stem(&f.path)?).map(str::to_string))
+                .collect();
+            for dir in fingerprint_dirs(&profile_dir.join(".fingerprint"), &removed_hashes) {
+                push_dir(
+                    &mut stats,
+                    sizes,
+                    mode,
+                    dir,
+                    profile,
+                    RemovalKind::Fingerprint,
+                );
+            }
+        }
+
+        Ok(stats)
+    }
+
+    pub(crate) async fn run(self) -> Result<()> {
+        let target_dir = self.resolve_target_dir()?;
+        log::debug!("Target directory: {}", target_dir.display());
+        if !target_dir.exists() {
+            eprintln!(
+                "⚠️  Warning: Target directory does not exist: {}",
+                target_dir.display()
+            );
+            return Ok(());
+        }
+
+        let trace = self.collect_traces(&target_dir).await?;
+        let deps_dirs = traced_deps_dirs(&trace.used_artifacts, &target_dir);
+        print_build_profiles(&deps_dirs);
+
+        let sizes = SizeCache::scan(
+            deps_dirs
+                .iter()
+                .filter_map(|(d, _)| d.parent())
+                .filter(|p| p.exists())
+                .map(Path::to_path_buf),
+        )
+        .await?;
+
+        let mut stats = CleanupStats::default();
+        for (deps_dir, profile) in &deps_dirs {
+            if deps_dir.exists() {
+                stats.merge_from(self.plan_profile(deps_dir, profile, &trace, &sizes).await?);
+            }
+        }
+
+        print_profile_breakdown(&stats);
+
+        let removed = if stats.files == 0 {
+            println!("✨ Nothing to prune: the cache only holds what this job used.");
+            None
+        } else {
+            super::confirm_and_remove(&stats, true, self.dry_run).await?
+        };
+
+        if let Some(path) = std::env::var_os(&self.summary_env).filter(|p| !p.is_empty()) {
+            let summary = markdown_summary(&stats, removed.as_ref(), self.dry_run);
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut f| f.write_all(summary.as_bytes()))
+                .with_context(|| {
+                    format!("writing step summary to {}", Path::new(&path).display())
+                })?;
+        }
+
+        if removed.is_some_and(|r| !r.errors.is_empty()) {
+            std::process::exit(EXIT_REMOVAL_FAILED);
+        }
+        Ok(())
+    }
+}
+
+fn push_dir(
+    stats: &mut CleanupStats,
+    sizes: &SizeCache,
+    mode: SizeMode,
+    path: PathBuf,
+    profile: &str,
+    kind: RemovalKind,
+) {
+    let dir_sizes = sizes.size_of(&path);
+    let size = dir_sizes.get(mode);
+    stats.add_reclaimable(profile, size, dir_sizes);
+    stats.dirs_to_remove.push(DirToRemove {
+        path,
+        size,
+        sizes: dir_sizes,
+        profile: profile.to_string(),
+        kind,
+    });
+}
+
+/// The unit hash of an artifact stem or fingerprint dir name (`foo-<hash>`).
+///
+/// Fingerprint dirs are named after the package (`my-crate-<hash>`) while
+/// artifacts use the crate name (`my_crate-<hash>`), so units are matched by
+/// hash alone.
+pub(super) fn unit_hash(name: &str) -> Option<&str> {
+    name.rsplit_once('-').map(|(_, hash)| hash)
+}
+
+/// Entries of a `.fingerprint/` directory whose unit hash is in `hashes`.
+fn fingerprint_dirs(fingerprint_root: &Path, hashes: &HashSet<String>) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(fingerprint_root) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .and_then(unit_hash)
+                .is_some_and(|h| hashes.contains(h))
+        })
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// A GitHub step summary: per-profile table plus the removal outcome.
+fn markdown_summary(
+    planned: &CleanupStats,
+    removed: Option<&CleanupStats>,
+    dry_run: bool,
+) -> String {
+    let mut md = String::new();
+    let _ = writeln!(md, "### 🧹 cargo-clean-artifact ci-cache\n");
+    let _ = writeln!(md, "| Profile | Kept | Pruned | Profile dir |");
+    let _ = writeln!(md, "|---|---:|---:|---:|");
+    let mut profiles: Vec<_> = planned.per_profile.iter().collect();
+    profiles.sort_by_key(|(name, _)| name.as_str());
+    for (name, stat) in profiles {
+        let _ = writeln!(
+            md,
+            "| `{name}` | {} | {} ({} items) | {} |",
+            format_bytes(stat.used_bytes),
+            format_bytes(stat.bytes),
+            stat.files,
+            format_bytes(stat.total_dir_bytes),
+        );
+    }
+    md.push('\n');
+
+    match removed {
+        Some(removed) => {
+            let _ = writeln!(
+                md,
+                "**Removed {} items ({})** before saving the cache.",
+                removed.files,
+                format_bytes(removed.bytes)
+            );
+            if !removed.errors.is_empty() {
+                let _ = writeln!(
+                    md,
+                    "\n⚠️ {} items could not be removed.",
+                    removed.errors.len()
+                );
+            }
+        }
+        None if dry_run && planned.files > 0 => {
+            let _ = writeln!(
+                md,
+                "**Dry-run:** would remove {} items ({}).",
+                planned.files,
+                format_bytes(planned.bytes)
+            );
+        }
+        None => {
+            let _ = writeln!(md, "Nothing to prune.");
+        }
+    }
+    for kind in planned.removal_kinds() {
+        let (count, bytes) = planned
+            .files_to_remove
+            .iter()
+            .filter(|f| f.kind == kind)
+            .map(|f| f.size)
+            .chain(
+                planned
+                    .dirs_to_remove
+                    .iter()
+                    .filter(|d| d.kind == kind)
+                    .map(|d| d.size),
+            )
+            .fold((0usize, 0u64), |(n, b), s| (n + 1, b + s));
+        let _ = writeln!(md, "- {}: {count} ({})", kind.label(), format_bytes(bytes));
+    }
+    md.push('\n');
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn fingerprints_are_matched_by_unit_hash() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in ["my-crate-aaa", "my-crate-bbb", "serde-ccc"] {
+            fs::create_dir_all(tmp.path().join(name)).unwrap();
+        }
+        // Artifacts use the crate name, fingerprints the package name
+        let removed: HashSet<String> = ["my_crate-aaa", "serde-ccc"]
+            .iter()
+            .filter_map(|stem| unit_hash(stem).map(str::to_string))
+            .collect();
+
+        assert_eq!(
+            fingerprint_dirs(tmp.path(), &removed),
+            vec![
+                tmp.path().join("my-crate-aaa"),
+                tmp.path().join("serde-ccc")
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_summary_lists_profiles_and_outcome() {
+        let mut planned = CleanupStats::default();
+        planned.add_reclaimable("debug", 2048, Default::default());
+        planned.per_profile.get_mut("debug").unwrap().used_bytes = 1024;
+        planned.dirs_to_remove.push(DirToRemove {
+            path: PathBuf::from("target/debug/incremental"),
+            size: 2048,
+            sizes: Default::default(),
+            profile: "debug".to_string(),
+            kind: RemovalKind::Incremental,
+        });
+
+        let md = markdown_summary(&planned, None, true);
+        assert!(md.contains("| `debug` | 1.00 KiB | 2.00 KiB (1 items) |"));
+        assert!(md.contains("**Dry-run:** would remove 1 items (2.00 KiB)."));
+        assert!(md.contains("- stale incremental dirs: 1 (2.00 KiB)"));
+
+        let md = markdown_summary(&planned, Some(&planned), false);
+        assert!(md.contains("**Removed 1 items (2.00 KiB)**"));
+    }
+}
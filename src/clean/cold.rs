@@ -0,0 +1,368 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::crate_deps::format_bytes;
+
+use super::manifest::TraceManifest;
+use super::scan::{SizeCache, SizeMode};
+use super::stats::{CleanupStats, DirToRemove, RemovalKind};
+
+/// One profile directory under the target dir, e.g. `debug` or
+/// `aarch64-unknown-linux-gnu/release`.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct ProfileDir {
+    /// The target or build dir holding it
+    pub(super) root: PathBuf,
+    pub(super) path: PathBuf,
+    pub(super) name: String,
+    pub(super) size: u64,
+    pub(super) modified: SystemTime,
+    /// Scanned by this run's trace
+    pub(super) traced: bool,
+    /// Referenced by a stored trace manifest
+    pub(super) in_manifest: bool,
+    pub(super) cold: bool,
+}
+
+/// Newest modification time of `dir` and its direct children: a build
+/// rewrites `deps/`, `.fingerprint/` and the uplifted outputs.
+fn last_modified(dir: &Path) -> SystemTime {
+    let mtime = |p: &Path| {
+        std::fs::symlink_metadata(p)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    };
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| mtime(&e.path()))
+        .fold(mtime(dir), SystemTime::max)
+}
+
+/// Profile directories of a target or build dir, with their names:
+/// `<profile>` and `<triple>/<profile>`.  Besides `deps/`, cargo's lock file
+/// marks them, as in the final target dir next to a separate build-dir and in
+/// the per-unit layout.
+pub(super) fn discover_profile_dirs(root: &Path) -> Vec<(PathBuf, String)> {
+    let children = |dir: &Path| -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .map(|e| e.path())
+            .collect();
+        dirs.sort();
+        dirs
+    };
+    let is_profile = |dir: &Path| {
+        ["deps", ".cargo-lock", ".cargo-build-lock"]
+            .iter()
+            .any(|marker| dir.join(marker).exists())
+    };
+    let mut found = Vec::new();
+    for level1 in children(root) {
+        let mut candidates = vec![level1.clone()];
+        candidates.extend(children(&level1));
+        for profile_dir in candidates {
+            if is_profile(&profile_dir)
+                && let Ok(rel) = profile_dir.strip_prefix(root)
+            {
+                let name = rel.to_string_lossy().into_owned();
+                found.push((profile_dir, name));
+            }
+        }
+    }
+    found
+}
+
+/// Every profile directory of `roots` (the target dir, and the build-dir when
+/// it is separate).  A profile is cold when this run did not trace it and it
+/// is older than `max_age` or no stored trace uses it; a profile counts as
+/// traced or stored in all roots when it is in one.
+pub(super) fn profile_dirs(
+    roots: &[&Path],
+    traced: &HashSet<PathBuf>,
+    manifests: &[TraceManifest],
+    sizes: &SizeCache,
+    mode: SizeMode,
+    max_age: Duration,
+    now: SystemTime,
+) -> Vec<ProfileDir> {
+    roots
+        .iter()
+        .flat_map(|root| {
+            discover_profile_dirs(root)
+                .into_iter()
+                .map(move |(path, name)| (root, path, name))
+        })
+        .map(|(root, path, name)| {
+            let same_profile: Vec<PathBuf> = roots.iter().map(|r| r.join(&name)).collect();
+            let modified = last_modified(&path);
+            let traced = same_profile.iter().any(|p| traced.contains(p));
+            let in_manifest = manifests.iter().any(|m| {
+                m.artifacts
+                    .iter()
+                    .any(|a| same_profile.iter().any(|p| a.starts_with(p)))
+            });
+            let old = now.duration_since(modified).is_ok_and(|age| age > max_age);
+            ProfileDir {
+                root: root.to_path_buf(),
+                size: sizes.size_of(&path).get(mode),
+                cold: !traced && (old || !in_manifest),
+                path,
+                name,
+                modified,
+                traced,
+                in_manifest,
+            }
+        })
+        .collect()
+}
+
+/// The directories to remove for the cold profiles: a whole target-triple
+/// directory when every profile in it is cold, otherwise each cold profile.
+pub(super) fn cold_dirs(profiles: &[ProfileDir]) -> Vec<(PathBuf, &str)> {
+    let mut by_triple: BTreeMap<(&Path, &str), Vec<&ProfileDir>> = BTreeMap::new();
+    let mut dirs = Vec::new();
+    for profile in profiles {
+        match profile.name.split_once('/') {
+            Some((triple, _)) => by_triple
+                .entry((profile.root.as_path(), triple))
+                .or_default()
+                .push(profile),
+            None if profile.cold => dirs.push((profile.path.clone(), profile.name.as_str())),
+            None => {}
+        }
+    }
+    for ((root, triple), profiles) in by_triple {
+        if profiles.iter().all(|p| p.cold) {
+            dirs.push((root.join(triple), triple));
+        } else {
+            dirs.extend(
+                profiles
+                    .into_iter()
+                    .filter(|p| p.cold)
+                    .map(|p| (p.path.clone(), p.name.as_str())),
+            );
+        }
+    }
+    dirs
+}
+
+impl CleanupStats {
+    /// Plan removal of `dirs` as cold profile directories.
+    pub(super) fn add_cold_dirs(
+        &mut self,
+        dirs: Vec<(PathBuf, &str)>,
+        sizes: &SizeCache,
+        mode: SizeMode,
+    ) {
+        for (path, name) in dirs {
+            let dir_sizes = sizes.size_of(&path);
+            let size = dir_sizes.get(mode);
+            self.add_reclaimable(name, size, dir_sizes);
+            self.dirs_to_remove.push(DirToRemove {
+                path,
+                size,
+                sizes: dir_sizes,
+                profile: name.to_string(),
+                kind: RemovalKind::ColdProfile,
+            });
+        }
+    }
+}
+
+/// `3d`, `5h` or `<1h`.
+fn format_age(age: Duration) -> String {
+    let hours = age.as_secs() / 3600;
+    match hours {
+        0 => "<1h".to_string(),
+        1..48 => format!("{hours}h"),
+        _ => format!("{}d", hours / 24),
+    }
+}
+
+pub(super) fn print_profile_dirs(profiles: &[ProfileDir], now: SystemTime) {
+    if profiles.is_empty() {
+        return;
+    }
+    println!("\x1b[1;36mProfile directories:\x1b[0m");
+    let width = profiles.iter().map(|p| p.name.len()).max().unwrap_or(0);
+    // Name the root once it changes, when a separate build-dir adds one
+    let several_roots = profiles
+        .iter()
+        .map(|p| &p.root)
+        .collect::<BTreeSet<_>>()
+        .len()
+        > 1;
+    let mut last_root = None;
+    for profile in profiles {
+        if several_roots && last_root != Some(&profile.root) {
+            println!("  \x1b[2m{}\x1b[0m", profile.root.display());
+            last_root = Some(&profile.root);
+        }
+        let age = now.duration_since(profile.modified).unwrap_or_default();
+        let status = if profile.traced {
+            "\x1b[32mtraced\x1b[0m"
+        } else if profile.cold {
+            "\x1b[33mcold\x1b[0m"
+        } else {
+            "\x1b[2min a stored trace\x1b[0m"
+        };
+        println!(
+            "  {}{:<width$}\x1b[0m  {:>10}  \x1b[2m{:>4} ago\x1b[0m  {status}",
+            crate::theme::profile_color(&profile.name),
+            profile.name,
+            format_bytes(profile.size),
+            format_age(age),
+        );
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn untraced_profiles_go_cold_by_age_or_missing_trace() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path();
+        for dir in [
+            "debug/deps",
+            "release/deps",
+            "profiling/deps",
+            "aarch64-unknown-linux-gnu/release/deps",
+            "wasm32-unknown-unknown/debug/deps",
+            "wasm32-unknown-unknown/release/deps",
+        ] {
+            fs::create_dir_all(target.join(dir)).unwrap();
+            fs::write(target.join(dir).join("libfoo-1.rlib"), b"rlib").unwrap();
+        }
+        let now = SystemTime::now();
+        let year = Duration::from_secs(365 * 24 * 3600);
+        let manifests = [TraceManifest {
+            command: "cargo build --release".to_string(),
+            recorded_at: 0,
+            artifacts: vec![
+                target.join("release/deps/libfoo-1.rlib"),
+                target.join("profiling/deps/libfoo-1.rlib"),
+                target.join("wasm32-unknown-unknown/release/deps/libfoo-1.rlib"),
+            ],
+            ..Default::default()
+        }];
+        let traced = HashSet::from([target.join("debug")]);
+        let sizes = SizeCache::scan([target.to_path_buf()]).await.unwrap();
+
+        let profiles = profile_dirs(
+            &[target],
+            &traced,
+            &manifests,
+            &sizes,
+            SizeMode::Apparent,
+            year,
+            now,
+        );
+        let cold: Vec<&str> = profiles
+            .iter()
+            .filter(|p| p.cold)
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(
+            cold,
+            [
+                "aarch64-unknown-linux-gnu/release",
+                "wasm32-unknown-unknown/debug"
+            ]
+        );
+
+        // Everything is older than a year from now on, except the traced debug
+        let later = profile_dirs(
+            &[target],
+            &traced,
+            &manifests,
+            &sizes,
+            SizeMode::Apparent,
+            year,
+            now + 2 * year,
+        );
+        let dirs: Vec<&str> = cold_dirs(&later)
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                "profiling",
+                "release",
+                "aarch64-unknown-linux-gnu",
+                "wasm32-unknown-unknown"
+            ]
+        );
+        // Only the cold profile when its triple still has a live one
+        let dirs: Vec<&str> = cold_dirs(&profiles)
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(
+            dirs,
+            ["aarch64-unknown-linux-gnu", "wasm32-unknown-unknown/debug"]
+        );
+    }
+
+    #[tokio::test]
+    async fn final_target_dir_profiles_are_found_next_to_a_build_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (build, target) = (tmp.path().join("bld"), tmp.path().join("target"));
+        // The build-dir in the per-unit layout, the target dir with outputs only
+        for (dir, marker) in [
+            (
+                build.join("debug/build/l/aaaa"),
+                build.join("debug/.cargo-build-lock"),
+            ),
+            (target.join("debug"), target.join("debug/.cargo-lock")),
+            (target.join("release"), target.join("release/.cargo-lock")),
+            (target.join("debug/examples"), target.join("debug/app")),
+        ] {
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(marker, b"").unwrap();
+        }
+        let traced = HashSet::from([build.join("debug")]);
+        let sizes = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+
+        let profiles = profile_dirs(
+            &[&build, &target],
+            &traced,
+            &[],
+            &sizes,
+            SizeMode::Apparent,
+            Duration::from_secs(3600),
+            SystemTime::now(),
+        );
+        let found: Vec<(&Path, &str, bool)> = profiles
+            .iter()
+            .map(|p| (p.path.as_path(), p.name.as_str(), p.cold))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (build.join("debug").as_path(), "debug", false),
+                (target.join("debug").as_path(), "debug", false),
+                (target.join("release").as_path(), "release", true),
+            ]
+        );
+        assert_eq!(cold_dirs(&profiles), [(target.join("release"), "release")]);
+    }
+
+    #[test]
+    fn ages_are_short() {
+        assert_eq!(format_age(Duration::from_secs(60)), "<1h");
+        assert_eq!(format_age(Duration::from_secs(5 * 3600)), "5h");
+        assert_eq!(format_age(Duration::from_secs(30 * 86_400)), "30d");
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::crate_deps::crate_key;
+
+use super::scan::artifact_stem;
+
+/// Extensions of the compiled crates a unit can consume.
+const LIBRARY_EXTENSIONS: &[&str] = &["rlib", "rmeta", "so", "dylib", "dll"];
+
+fn is_library(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| LIBRARY_EXTENSIONS.contains(&e))
+}
+
+/// Split a Makefile dependency list on unescaped spaces (rustc writes a space
+/// in a path as `\ `).
+fn split_paths(list: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut current = String::new();
+    let mut chars = list.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => current.push(chars.next().unwrap_or(' ')),
+            ' ' | '\t' => {
+                if !current.is_empty() {
+                    paths.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        paths.push(current);
+    }
+    paths
+}
+
+/// The `output: inputs…` rules of a dep-info (`.d`) file. Relative inputs are
+/// resolved against `base`, the directory rustc ran in.
+fn parse_dep_info(text: &str, base: &Path) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    let text = text.replace("\\\n", " ");
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            line.split_once(": ")
+                .or_else(|| line.strip_suffix(':').map(|o| (o, "")))
+        })
+        .map(|(output, inputs)| {
+            let inputs = split_paths(inputs)
+                .into_iter()
+                .map(|p| base.join(p))
+                .collect();
+            (PathBuf::from(output.replace("\\ ", " ")), inputs)
+        })
+        .collect()
+}
+
+/// Which artifacts each unit in `deps/` was built from, as recorded in its
+/// dep-info file.
+///
+/// Source files are always listed. Compiled crates (`.rlib`, `.rmeta`, …)
+/// are only listed when rustc ran with `-Zbinary-dep-depinfo`; without them
+/// the graph has no edges and callers fall back to the trace.
+#[derive(Debug, Default)]
+pub(super) struct DepGraph {
+    /// Consumer artifact → the crates it was built against
+    deps: HashMap<PathBuf, BTreeSet<PathBuf>>,
+    /// Crate artifact → the artifacts built against it
+    consumers: HashMap<PathBuf, BTreeSet<PathBuf>>,
+    /// Unit stem → its source files
+    sources: HashMap<String, BTreeSet<PathBuf>>,
+    /// Unit stem → the dep-info file describing it
+    dep_info: HashMap<String, PathBuf>,
+}
+
+impl DepGraph {
+    /// Read every `.d` file in `deps_dirs`; relative sources are resolved
+    /// against `workspace_root`.
+    pub(super) fn load<'a>(
+        deps_dirs: impl IntoIterator<Item = &'a Path>,
+        workspace_root: &Path,
+    ) -> Self {
+        let mut graph = DepGraph::default();
+        for deps_dir in deps_dirs {
+            for path in std::fs::read_dir(deps_dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "d"))
+            {
+                let Ok(text) = std::fs::read_to_string(&path) else {
+                    continue;
+                };
+                graph.add(&path, &text, workspace_root);
+            }
+        }
+        graph
+    }
+
+    fn add(&mut self, dep_info: &Path, text: &str, base: &Path) {
+        let Some(stem) = artifact_stem(dep_info) else {
+            return;
+        };
+        let rules = parse_dep_info(text, base);
+        // The rule for the `.d` file itself repeats the inputs of the outputs
+        for (output, inputs) in &rules {
+            if output.extension().is_some_and(|e| e == "d") || inputs.is_empty() {
+                continue;
+            }
+            for input in inputs {
+                if is_library(input) {
+                    self.deps
+                        .entry(output.clone())
+                        .or_default()
+                        .insert(input.clone());
+                    self.consumers
+                        .entry(input.clone())
+                        .or_default()
+                        .insert(output.clone());
+                } else {
+                    self.sources
+                        .entry(stem.clone())
+                        .or_default()
+                        .insert(input.clone());
+                }
+            }
+        }
+        self.dep_info.insert(stem, dep_info.to_path_buf());
+    }
+
+    /// Source files the unit `stem` was built from.
+    pub(super) fn sources_of(&self, stem: &str) -> impl Iterator<Item = &Path> {
+        self.sources
+            .get(stem)
+            .into_iter()
+            .flatten()
+            .map(PathBuf::as_path)
+    }
+
+    /// Whether any dep-info listed compiled crates (`-Zbinary-dep-depinfo`).
+    pub(super) fn has_edges(&self) -> bool {
+        !self.consumers.is_empty()
+    }
+
+    /// Crate names of the units built directly against `artifact`.
+    pub(super) fn used_by(&self, artifact: &Path) -> HashSet<String> {
+        self.consumers
+            .get(artifact)
+            .into_iter()
+            .flatten()
+            .map(|c| crate_key(c))
+            .collect()
+    }
+
+    /// Every artifact built against `artifact`, directly or not.
+    pub(super) fn dependents(&self, artifact: &Path) -> BTreeSet<PathBuf> {
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![artifact.to_path_buf()];
+        while let Some(path) = stack.pop() {
+            for consumer in self.consumers.get(&path).into_iter().flatten() {
+                if seen.insert(consumer.clone()) {
+                    stack.push(consumer.clone());
+                }
+            }
+        }
+        seen
+    }
+
+    /// Artifacts on disk whose crate name is `name`.
+    pub(super) fn artifacts_of(&self, name: &str) -> BTreeSet<PathBuf> {
+        let name = name.replace('-', "_");
+        self.deps
+            .keys()
+            .chain(self.consumers.keys())
+            .filter(|p| crate_key(p) == name && p.exists())
+            .cloned()
+            .collect()
+    }
+
+    /// `(dep-info file, missing sources)` of every unit built from source
+    /// files that no longer exist, sorted by path.
+    pub(super) fn missing_sources(&self) -> Vec<(&Path, Vec<&Path>)> {
+        let mut missing: Vec<(&Path, Vec<&Path>)> = self
+            .sources
+            .iter()
+            .filter_map(|(stem, sources)| {
+                let gone: Vec<&Path> = sources
+                    .iter()
+                    .filter(|s| !s.exists())
+                    .map(PathBuf::as_path)
+                    .collect();
+                let dep_info = self.dep_info.get(stem)?;
+                (!gone.is_empty()).then_some((dep_info.as_path(), gone))
+            })
+            .collect();
+        missing.sort();
+        missing
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// List units whose recorded sources are gone: a deleted module or a removed
+/// path dependency. Their artifacts can't be rebuilt as they are.
+pub(super) fn print_missing_sources(graph: &DepGraph) {
+    let missing = graph.missing_sources();
+    if missing.is_empty() {
+        return;
+    }
+    println!(
+        "\x1b[1;33m👻 Built from sources that no longer exist\x1b[0m \x1b[2m({} units):\x1b[0m",
+        missing.len()
+    );
+    for (dep_info, sources) in missing.iter().take(10) {
+        let stem = artifact_stem(dep_info).unwrap_or_default();
+        let first = sources.first().map(|s| s.display().to_string());
+        let more = if sources.len() > 1 {
+            format!(" \x1b[2m(+{} more)\x1b[0m", sources.len() - 1)
+        } else {
+            String::new()
+        };
+        println!(
+            "  \x1b[1;36m{stem}\x1b[0m  \x1b[2m{}\x1b[0m{more}",
+            first.unwrap_or_default()
+        );
+    }
+    if missing.len() > 10 {
+        println!(
+            "{}",
+            crate::theme::format_more_line(missing.len() - 10, "units")
+        );
+    }
+    println!();
+}
+
+/// Print what is built against the artifacts of crate `name`, directly or not.
+pub(super) fn print_dependents(graph: &DepGraph, name: &str) {
+    if !graph.has_edges() {
+        println!(
+            "\x1b[1;33m⚠️  No crate dependencies in the dep-info files:\x1b[0m build with \
+             RUSTFLAGS=\"-Zbinary-dep-depinfo\" (nightly) to record them"
+        );
+        println!();
+        return;
+    }
+    let artifacts = graph.artifacts_of(name);
+    if artifacts.is_empty() {
+        println!("No artifacts of {name} found in the traced profiles");
+        println!();
+        return;
+    }
+    println!("\x1b[1;36m🔗 Built against {name}:\x1b[0m");
+    for artifact in artifacts {
+        let dependents = graph.dependents(&artifact);
+        println!(
+            "  {}  \x1b[2m{} dependents\x1b[0m",
+            file_name(&artifact),
+            dependents.len()
+        );
+        for dependent in dependents {
+            println!("      {}", file_name(&dependent));
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn dep_info_rules_are_parsed_with_escapes() {
+        let text = concat!(
+            "/t/debug/deps/foo-1.d: src/lib.rs /my\\ dir/x.rs \\\n /abs/y.rs\n",
+            "\n",
+            "/t/debug/deps/libfoo-1.rlib: src/lib.rs /t/debug/deps/libbar-2.rmeta\n",
+            "\n",
+            "# env-dep:CARGO_PKG_NAME=foo\n",
+            "src/lib.rs:\n",
+        );
+        let rules = parse_dep_info(text, Path::new("/ws"));
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[0].1,
+            [
+                PathBuf::from("/ws/src/lib.rs"),
+                PathBuf::from("/my dir/x.rs"),
+                PathBuf::from("/abs/y.rs")
+            ]
+        );
+        assert_eq!(rules[1].0, PathBuf::from("/t/debug/deps/libfoo-1.rlib"));
+        assert!(rules[2].1.is_empty());
+    }
+
+    #[test]
+    fn binary_deps_give_consumers_and_missing_sources_are_flagged() {
+        let tmp = tempfile::tempdir().unwrap();
+        let deps = tmp.path().join("debug/deps");
+        fs::create_dir_all(&deps).unwrap();
+        fs::write(tmp.path().join("lib.rs"), "").unwrap();
+        let d = |name: &str| deps.join(name).display().to_string();
+        for (unit, body) in [
+            ("base-1", format!("{}: lib.rs\n", d("libbase-1.rlib"))),
+            (
+                "mid-2",
+                format!("{}: lib.rs {}\n", d("libmid-2.rlib"), d("libbase-1.rlib")),
+            ),
+            (
+                "app-3",
+                format!("{}: gone.rs {}\n", d("app-3"), d("libmid-2.rlib")),
+            ),
+        ] {
+            fs::write(deps.join(format!("{unit}.d")), body).unwrap();
+        }
+        for artifact in ["libbase-1.rlib", "libmid-2.rlib", "app-3"] {
+            fs::write(deps.join(artifact), "").unwrap();
+        }
+
+        let graph = DepGraph::load([deps.as_path()], tmp.path());
+        let base = deps.join("libbase-1.rlib");
+        assert_eq!(graph.used_by(&base), HashSet::from(["mid".to_string()]));
+        assert_eq!(
+            graph.dependents(&base),
+            BTreeSet::from([deps.join("libmid-2.rlib"), deps.join("app-3")])
+        );
+        assert_eq!(graph.artifacts_of("base"), BTreeSet::from([base]));
+
+        let missing = graph.missing_sources();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].0, deps.join("app-3.d"));
+        assert_eq!(missing[0].1, [tmp.path().join("gone.rs")]);
+    }
+}
@@ -1,10 +1,25 @@
 use std::io::{self, IsTerminal};
+use std::path::PathBuf;
 
 use anstyle::{AnsiColor, Style};
 
 use crate::crate_deps::{format_bytes, paint};
 
-use super::stats::CleanupStats;
+use super::stats::{CleanupStats, UnclassifiedAction};
+
+/// `📂 Build profiles: debug, release` for the `deps/` dirs about to be scanned.
+pub(super) fn print_build_profiles(deps_dirs: &[(PathBuf, String)]) {
+    if deps_dirs.is_empty() {
+        println!("📂 Build profiles: \x1b[2m(none detected)\x1b[0m");
+    } else {
+        let profile_list: Vec<&str> = deps_dirs.iter().map(|(_, p)| p.as_str()).collect();
+        println!(
+            "📂 Build profiles: \x1b[1;36m{}\x1b[0m",
+            profile_list.join("\x1b[0m, \x1b[1;36m")
+        );
+    }
+    println!();
+}
 
 pub(super) fn print_profile_breakdown(stats: &CleanupStats) {
     if stats.per_profile.is_empty() {
@@ -15,20 +30,33 @@ pub(super) fn print_profile_breakdown(stats: &CleanupStats) {
 
     println!("{}", paint(color, "By profile:", header_style));
     for (profile, profile_stat) in &stats.per_profile {
+        let retained = if profile_stat.retained_bytes > 0 {
+            format!(" + {} retained", format_bytes(profile_stat.retained_bytes))
+        } else {
+            String::new()
+        };
         let total_suffix = if profile_stat.total_dir_bytes > 0 {
             format!(
-                "  \x1b[2m[{} kept / {} total dir]\x1b[0m",
+                "  \x1b[2m[{} kept{retained} / {} total dir]\x1b[0m",
                 format_bytes(profile_stat.used_bytes),
                 format_bytes(profile_stat.total_dir_bytes),
             )
         } else {
             String::new()
         };
+        let debuginfo = if profile_stat.debuginfo_bytes > 0 {
+            format!(
+                " \x1b[2m({} debuginfo)\x1b[0m",
+                format_bytes(profile_stat.debuginfo_bytes)
+            )
+        } else {
+            String::new()
+        };
         let to_remove = if profile_stat.files > 0 {
             format!(
-                "  \x1b[31m-{} files ({})\x1b[0m",
+                "  \x1b[31m-{} files ({})\x1b[0m{debuginfo}",
                 profile_stat.files,
-                format_bytes(profile_stat.bytes)
+                format_both_sizes(profile_stat.disk_bytes, profile_stat.apparent_bytes)
             )
         } else {
             String::new()
@@ -44,6 +72,122 @@ pub(super) fn print_profile_breakdown(stats: &CleanupStats) {
     println!();
 }
 
+pub(super) fn print_toolchain_breakdown(stats: &CleanupStats) {
+    if stats.per_toolchain.is_empty() {
+        return;
+    }
+    let color = io::stdout().is_terminal();
+    let header_style = Style::new().fg_color(Some(AnsiColor::Cyan.into())).bold();
+    let current_style = Style::new().fg_color(Some(AnsiColor::Green.into()));
+    let other_style = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+
+    let mut toolchains: Vec<_> = stats.per_toolchain.iter().collect();
+    toolchains.sort_by_key(|(name, stat)| (std::cmp::Reverse(stat.bytes), name.to_string()));
+
+    println!("{}", paint(color, "By toolchain (deps/):", header_style));
+    for (toolchain, stat) in toolchains {
+        let is_current = stats.current_toolchain.as_deref() == Some(toolchain.as_str());
+        let label = if is_current {
+            paint(color, format!("{toolchain} (current)"), current_style)
+        } else {
+            paint(color, toolchain, other_style)
+        };
+        println!(
+            "  {label}: {} files ({})",
+            stat.files,
+            format_bytes(stat.bytes)
+        );
+    }
+    println!();
+}
+
+/// Remind the user that a `--no-trace` plan is a guess, not a traced result.
+pub(super) fn print_estimate_notice(stats: &CleanupStats) {
+    if !stats.estimated {
+        return;
+    }
+    let color = io::stdout().is_terminal();
+    let warn_style = Style::new().fg_color(Some(AnsiColor::Yellow.into())).bold();
+    let dim_style = Style::new().fg_color(Some(AnsiColor::BrightBlack.into()));
+
+    println!(
+        "{} planned without tracing a build (--no-trace).",
+        paint(color, "⚠️  Estimate:", warn_style)
+    );
+    println!(
+        "{}",
+        paint(
+            color,
+            "   Only the newest build of each crate version is kept; the next build may recompile some units.",
+            dim_style
+        )
+    );
+    if !stats.unlocked_crates.is_empty() {
+        let names: Vec<&str> = stats.unlocked_crates.iter().map(String::as_str).collect();
+        println!(
+            "   Crates no longer in the resolve: {}",
+            paint(color, names.join(", "), warn_style)
+        );
+    }
+    println!();
+}
+
+/// List `deps/` files without a `crate-HASH` stem and what the rules decided.
+pub(super) fn print_unclassified(stats: &CleanupStats) {
+    if stats.unclassified.is_empty() {
+        return;
+    }
+    let color = io::stdout().is_terminal();
+    let header_style = Style::new().fg_color(Some(AnsiColor::Cyan.into())).bold();
+    let dim_style = Style::new().fg_color(Some(AnsiColor::BrightBlack.into()));
+
+    let mut files: Vec<_> = stats.unclassified.iter().collect();
+    files.sort_by_key(|f| std::cmp::Reverse(f.size));
+    let reported: Vec<_> = files
+        .iter()
+        .filter(|f| f.action == UnclassifiedAction::Report)
+        .collect();
+    println!(
+        "{} {}",
+        paint(color, "Unclassified (no crate-HASH name):", header_style),
+        paint(
+            color,
+            format!(
+                "{} files, {} left alone ({})",
+                files.len(),
+                reported.len(),
+                format_bytes(reported.iter().map(|f| f.size).sum())
+            ),
+            dim_style
+        )
+    );
+    for file in files.iter().take(10) {
+        let name = file
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("?");
+        let action = match &file.action {
+            UnclassifiedAction::Keep(reason) => format!("kept: {reason}"),
+            UnclassifiedAction::Report => "not removed".to_string(),
+        };
+        println!(
+            "  {}{}\x1b[0m  {name}  {}  {}",
+            crate::theme::profile_color(&file.profile),
+            file.profile,
+            format_bytes(file.size),
+            paint(color, action, dim_style)
+        );
+    }
+    if files.len() > 10 {
+        println!(
+            "{}",
+            crate::theme::format_more_line(files.len() - 10, "unclassified files")
+        );
+    }
+    println!();
+}
+
 pub(super) fn print_detailed_summary(stats: &CleanupStats) {
     let color = io::stdout().is_terminal();
     let header_style = Style::new().fg_color(Some(AnsiColor::Cyan.into())).bold();
@@ -53,14 +197,14 @@ pub(super) fn print_detailed_summary(stats: &CleanupStats) {
 
     println!();
     println!(
-        "{} {}{}",
+        "{} {}{}{}",
         paint(color, "📊 Summary:", header_style),
         paint(
             color,
             format!(
                 "{} files ({}) can be removed",
                 stats.files,
-                format_bytes(stats.bytes)
+                format_both_sizes(stats.disk_bytes, stats.apparent_bytes)
             ),
             size_style
         ),
@@ -72,6 +216,19 @@ pub(super) fn print_detailed_summary(stats: &CleanupStats) {
             )
         } else {
             String::new()
+        },
+        if stats.retained_builds > 0 {
+            paint(
+                color,
+                format!(
+                    "  •  {} retained in {} older builds",
+                    format_bytes(stats.retained_bytes),
+                    stats.retained_builds
+                ),
+                dim_style,
+            )
+        } else {
+            String::new()
         }
     );
     println!();
@@ -92,8 +249,13 @@ pub(super) fn print_detailed_summary(stats: &CleanupStats) {
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or("unknown");
+        let unit = file_info
+            .unit
+            .as_ref()
+            .map(|u| format!("  \x1b[2m{u}\x1b[0m"))
+            .unwrap_or_default();
         println!(
-            "{}",
+            "{}{unit}",
             crate::theme::format_artifact_line(
                 i + 1,
                 &file_info.profile,
@@ -123,7 +285,11 @@ pub(super) fn print_removal_summary(stats: &CleanupStats) {
         "{} {} files ({}) across {} crates",
         paint(color, "✓ Removed", success_style),
         paint(color, stats.files.to_string(), accent_style),
-        paint(color, format_bytes(stats.bytes), accent_style),
+        paint(
+            color,
+            format_both_sizes(stats.disk_bytes, stats.apparent_bytes),
+            accent_style
+        ),
         paint(color, stats.per_crate.len().to_string(), accent_style),
     );
 
@@ -141,23 +307,54 @@ pub(super) fn print_dry_run_summary(stats: &CleanupStats) {
         "{} would remove {} files ({}) across {} crates",
         paint(color, "Dry-run:", dry_run_style),
         paint(color, stats.files.to_string(), accent_style),
-        paint(color, format_bytes(stats.bytes), accent_style),
+        paint(
+            color,
+            format_both_sizes(stats.disk_bytes, stats.apparent_bytes),
+            accent_style
+        ),
         paint(color, stats.per_crate.len().to_string(), accent_style),
     );
 
     print_top_crates(stats, color);
 }
 
+/// Both size measures side by side, e.g. `1.20 MiB on disk, 1.50 MiB apparent`.
+fn format_both_sizes(disk: u64, apparent: u64) -> String {
+    format!(
+        "{} on disk, {} apparent",
+        format_bytes(disk),
+        format_bytes(apparent)
+    )
+}
+
 fn print_top_crates(stats: &CleanupStats, color: bool) {
     let mut crates: Vec<_> = stats.per_crate.iter().collect();
     crates.sort_by_key(|(name, stat)| (std::cmp::Reverse(stat.bytes), name.to_string()));
 
     let accent_style = Style::new().fg_color(Some(AnsiColor::Cyan.into()));
 
+    let dim_style = Style::new().dimmed();
+
     const MAX_CRATES: usize = 20;
+    const MAX_UNITS: usize = 3;
     for (name, stat) in crates.iter().take(MAX_CRATES) {
+        let mut units: Vec<&str> = stat
+            .units
+            .iter()
+            .take(MAX_UNITS)
+            .map(String::as_str)
+            .collect();
+        let more = format!("+{}", stat.units.len().saturating_sub(MAX_UNITS));
+        if stat.units.len() > MAX_UNITS {
+            units.push(&more);
+        }
+        let units = if units.is_empty() {
+            String::new()
+        } else {
+            format!("  {}", paint(color, units.join("; "), dim_style))
+        };
         println!(
-            "  - {}: {} files ({})",
+            "  - {}: {} files ({}){units}",
             paint(color, name, accent_style),
             paint(color, stat.files.to_string(), accent_style),
             paint(color, format_bytes(stat.bytes), accent_style)
@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use cargo_metadata::{Metadata, Package, PackageId};
+
+use crate::crate_deps::{crate_key, format_bytes};
+
+use super::build_dir::artifact_dirs;
+use super::scan::{SizeCache, SizeMode, artifact_stem};
+
+/// One version of a crate that is locked in several versions.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct DupeVersion {
+    pub(super) version: String,
+    /// Builds (hashes) of this version across all profiles
+    pub(super) builds: usize,
+    pub(super) bytes: u64,
+    /// Workspace members that depend on it, directly or not
+    pub(super) members: BTreeSet<String>,
+    /// Packages that depend on it directly
+    pub(super) via: BTreeSet<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) struct DuplicateCrate {
+    pub(super) name: String,
+    pub(super) versions: Vec<DupeVersion>,
+}
+
+/// Name of the lib target as it appears in artifact file names.
+fn lib_crate_name(package: &Package) -> String {
+    package
+        .targets
+        .iter()
+        .find(|t| {
+            t.kind
+                .iter()
+                .any(|k| k.ends_with("lib") || k == "proc-macro")
+        })
+        .map_or(package.name.as_str(), |t| t.name.as_str())
+        .replace('-', "_")
+}
+
+/// Which of `package_dirs` the sources listed in a dep-info (`.d`) file
+/// belong to.
+fn version_index(dep_info: &str, package_dirs: &[PathBuf]) -> Option<usize> {
+    package_dirs.iter().position(|dir| {
+        let mut prefix = dir.to_string_lossy().into_owned();
+        prefix.push(std::path::MAIN_SEPARATOR);
+        dep_info.contains(&prefix)
+    })
+}
+
+/// For every package reachable from a member: the members that reach it and
+/// the packages that depend on it directly.
+fn pulled_in_by<'a>(
+    edges: &HashMap<&'a PackageId, Vec<&'a PackageId>>,
+    members: &[(&'a PackageId, String)],
+) -> HashMap<&'a PackageId, (BTreeSet<String>, BTreeSet<&'a PackageId>)> {
+    let mut result: HashMap<&PackageId, (BTreeSet<String>, BTreeSet<&PackageId>)> = HashMap::new();
+    for (id, deps) in edges {
+        for dep in deps {
+            result.entry(dep).or_default().1.insert(id);
+        }
+    }
+    for (member, name) in members {
+        let mut stack = vec![*member];
+        let mut seen = BTreeSet::new();
+        while let Some(id) = stack.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            for dep in edges.get(id).into_iter().flatten() {
+                result.entry(dep).or_default().0.insert(name.clone());
+                stack.push(dep);
+            }
+        }
+    }
+    result
+}
+
+/// Crates locked in more than one version, with the on-disk footprint of
+/// each version's builds in `build_dir`, largest first.
+pub(super) async fn find_duplicates(
+    metadata: &Metadata,
+    build_dir: &Path,
+    mode: SizeMode,
+) -> Result<Vec<DuplicateCrate>> {
+    let mut by_name: BTreeMap<&str, Vec<&Package>> = BTreeMap::new();
+    for package in &metadata.packages {
+        by_name.entry(&package.name).or_default().push(package);
+    }
+    by_name.retain(|_, packages| packages.len() > 1);
+    if by_name.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let edges: HashMap<&PackageId, Vec<&PackageId>> = metadata
+        .resolve
+        .iter()
+        .flat_map(|r| &r.nodes)
+        .map(|node| (&node.id, node.dependencies.iter().collect()))
+        .collect();
+    let package_names: HashMap<&PackageId, &str> = metadata
+        .packages
+        .iter()
+        .map(|p| (&p.id, p.name.as_str()))
+        .collect();
+    let members: Vec<(&PackageId, String)> = metadata
+        .workspace_members
+        .iter()
+        .map(|id| {
+            (
+                id,
+                package_names.get(id).copied().unwrap_or("?").to_string(),
+            )
+        })
+        .collect();
+    let dependents = pulled_in_by(&edges, &members);
+
+    let deps_dirs = artifact_dirs(build_dir);
+    let sizes = SizeCache::scan(deps_dirs.iter().map(|(d, _)| d.clone())).await?;
+    // crate name → stem → files, for every build on disk
+    let mut on_disk: HashMap<String, BTreeMap<(PathBuf, String), Vec<PathBuf>>> = HashMap::new();
+    for (deps_dir, _) in &deps_dirs {
+        let Ok(entries) = std::fs::read_dir(deps_dir) else {
+            continue;
+        };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            let Some(stem) = artifact_stem(&path) else {
+                continue;
+            };
+            on_disk
+                .entry(crate_key(&path))
+                .or_default()
+                .entry((deps_dir.clone(), stem))
+                .or_default()
+                .push(path);
+        }
+    }
+
+    let mut duplicates = Vec::new();
+    for (name, mut packages) in by_name {
+        packages.sort_by(|a, b| a.version.cmp(&b.version));
+        let dirs: Vec<PathBuf> = packages
+            .iter()
+            .map(|p| {
+                p.manifest_path
+                    .parent()
+                    .map_or_else(PathBuf::new, |d| d.as_std_path().to_path_buf())
+            })
+            .collect();
+        let mut versions: Vec<DupeVersion> = packages
+            .iter()
+            .map(|p| {
+                let (members, via) = dependents.get(&p.id).cloned().unwrap_or_default();
+                DupeVersion {
+                    version: p.version.to_string(),
+                    members,
+                    via: via
+                        .into_iter()
+                        .filter_map(|id| package_names.get(id).map(|n| n.to_string()))
+                        .collect(),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let key = lib_crate_name(packages[0]);
+        for ((deps_dir, stem), files) in on_disk.get(&key).into_iter().flatten() {
+            let dep_info =
+                std::fs::read_to_string(deps_dir.join(format!("{stem}.d"))).unwrap_or_default();
+            let Some(index) = version_index(&dep_info, &dirs) else {
+                continue;
+            };
+            versions[index].builds += 1;
+            versions[index].bytes += files
+                .iter()
+                .map(|f| sizes.size_of(f).get(mode))
+                .sum::<u64>();
+        }
+        duplicates.push(DuplicateCrate {
+            name: name.to_string(),
+            versions,
+        });
+    }
+    duplicates.sort_by_key(|d| std::cmp::Reverse(d.versions.iter().map(|v| v.bytes).sum::<u64>()));
+    Ok(duplicates)
+}
+
+pub(super) fn print_duplicates(duplicates: &[DuplicateCrate]) {
+    if duplicates.is_empty() {
+        println!("\x1b[1;32m✓ No duplicate dependency versions\x1b[0m in the lockfile");
+        println!();
+        return;
+    }
+    println!(
+        "\x1b[1;33m👯 Duplicate dependency versions\x1b[0m \x1b[2m({} crates):\x1b[0m",
+        duplicates.len()
+    );
+    for dupe in duplicates {
+        let total: u64 = dupe.versions.iter().map(|v| v.bytes).sum();
+        println!(
+            "  \x1b[1;36m{}\x1b[0m  {} versions  \x1b[2m{}\x1b[0m",
+            dupe.name,
+            dupe.versions.len(),
+            format_bytes(total)
+        );
+        for version in &dupe.versions {
+            let footprint = if version.builds == 0 {
+                format!("\x1b[2m{:<24}\x1b[0m", "not built")
+            } else {
+                format!(
+                    "{:<24}",
+                    format!(
+                        "{} in {} builds",
+                        format_bytes(version.bytes),
+                        version.builds
+                    )
+                )
+            };
+            let members: Vec<&str> = version.members.iter().map(String::as_str).collect();
+            let via: Vec<&str> = version.via.iter().map(String::as_str).collect();
+            println!(
+                "      v{:<12} {footprint} \x1b[2mvia\x1b[0m {}  \x1b[2mfor\x1b[0m {}",
+                version.version,
+                via.join(", "),
+                if members.is_empty() {
+                    "-".to_string()
+                } else {
+                    members.join(", ")
+                }
+            );
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str) -> PackageId {
+        PackageId {
+            repr: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn builds_are_attributed_by_source_dir() {
+        let dirs = [
+            PathBuf::from("/registry/syn-1.0.109"),
+            PathBuf::from("/registry/syn-2.0.48"),
+        ];
+        let dep_info = "/t/debug/deps/libsyn-abc.rlib: /registry/syn-2.0.48/src/lib.rs\n";
+        assert_eq!(version_index(dep_info, &dirs), Some(1));
+        // A directory name that is a prefix of another's must not match it
+        let dirs = [PathBuf::from("/registry/syn-2.0.4")];
+        assert_eq!(version_index(dep_info, &dirs), None);
+    }
+
+    #[test]
+    fn members_and_direct_dependents_are_collected() {
+        let (app, cli, serde, syn1, syn2) =
+            (id("app"), id("cli"), id("serde"), id("syn1"), id("syn2"));
+        let edges: HashMap<&PackageId, Vec<&PackageId>> = HashMap::from([
+            (&app, vec![&serde]),
+            (&cli, vec![&syn2]),
+            (&serde, vec![&syn1]),
+        ]);
+        let members = [(&app, "app".to_string()), (&cli, "cli".to_string())];
+
+        let pulled = pulled_in_by(&edges, &members);
+        let (syn1_members, syn1_via) = &pulled[&syn1];
+        assert_eq!(syn1_members, &BTreeSet::from(["app".to_string()]));
+        assert_eq!(syn1_via, &BTreeSet::from([&serde]));
+        assert_eq!(pulled[&syn2].0, BTreeSet::from(["cli".to_string()]));
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::crate_deps::format_bytes;
+
+use super::manifest::TraceManifest;
+use super::scan::{SizeCache, SizeMode, artifact_stem};
+
+/// One build (hash) of a crate in a `deps/` directory.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct HashBuild {
+    pub(super) hash: String,
+    pub(super) bytes: u64,
+    /// Crates whose compilation referenced this build
+    pub(super) users: BTreeSet<String>,
+    /// Stored traced commands that used this build
+    pub(super) commands: BTreeSet<String>,
+}
+
+/// A crate with more builds side by side in one profile than the threshold.
+#[derive(Debug, PartialEq)]
+pub(super) struct FlappingCrate {
+    pub(super) profile: String,
+    pub(super) name: String,
+    pub(super) builds: Vec<HashBuild>,
+}
+
+/// Group the files of `deps_dir` by crate and report the crates with more
+/// than `threshold` distinct hashes, most builds first.
+///
+/// `used_by` attributes each hash to the crates that referenced it in this
+/// trace; `manifests` to every stored command that used it.
+pub(super) fn find_flapping(
+    deps_dir: &Path,
+    profile: &str,
+    used_by: &HashMap<PathBuf, HashSet<String>>,
+    manifests: &[TraceManifest],
+    sizes: &SizeCache,
+    mode: SizeMode,
+    threshold: usize,
+) -> Vec<FlappingCrate> {
+    let Ok(entries) = std::fs::read_dir(deps_dir) else {
+        return Vec::new();
+    };
+    // artifact -> stored commands that used it
+    let mut commands_of: HashMap<&Path, BTreeSet<&str>> = HashMap::new();
+    for manifest in manifests {
+        for artifact in &manifest.artifacts {
+            commands_of
+                .entry(artifact)
+                .or_default()
+                .insert(&manifest.command);
+        }
+    }
+    let mut by_crate: BTreeMap<String, BTreeMap<String, HashBuild>> = BTreeMap::new();
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let Some((name, hash)) = artifact_stem(&path).and_then(|stem| {
+            stem.rsplit_once('-')
+                .map(|(n, h)| (n.to_string(), h.to_string()))
+        }) else {
+            continue;
+        };
+        let build = by_crate
+            .entry(name)
+            .or_default()
+            .entry(hash.clone())
+            .or_insert_with(|| HashBuild {
+                hash,
+                ..Default::default()
+            });
+        build.bytes += sizes.size_of(&path).get(mode);
+        if let Some(users) = used_by.get(&path) {
+            build.users.extend(users.iter().cloned());
+        }
+        if let Some(commands) = commands_of.get(path.as_path()) {
+            build
+                .commands
+                .extend(commands.iter().map(|c| c.to_string()));
+        }
+    }
+
+    let mut flapping: Vec<FlappingCrate> = by_crate
+        .into_iter()
+        .filter(|(_, builds)| builds.len() > threshold)
+        .map(|(name, builds)| FlappingCrate {
+            profile: profile.to_string(),
+            name,
+            builds: builds.into_values().collect(),
+        })
+        .collect();
+    flapping.sort_by_key(|c| std::cmp::Reverse(c.builds.len()));
+    flapping
+}
+
+/// Whether the workspace manifest at `manifest_path` still resolves features
+/// with resolver 1 (no `resolver` key and an edition before 2021).
+pub(super) fn uses_resolver_v1(manifest_path: &Path) -> bool {
+    let Ok(text) = std::fs::read_to_string(manifest_path) else {
+        return false;
+    };
+    let Ok(manifest) = text.parse::<toml::Table>() else {
+        return false;
+    };
+    let resolver = manifest
+        .get("workspace")
+        .and_then(|w| w.get("resolver"))
+        .or_else(|| manifest.get("package").and_then(|p| p.get("resolver")))
+        .and_then(|r| r.as_str());
+    if let Some(resolver) = resolver {
+        return resolver == "1";
+    }
+    let edition = manifest
+        .get("package")
+        .and_then(|p| p.get("edition"))
+        .and_then(|e| e.as_str())
+        .unwrap_or("2015");
+    edition < "2021"
+}
+
+pub(super) fn print_flapping(crates: &[FlappingCrate], threshold: usize, resolver_v1: bool) {
+    if crates.is_empty() {
+        println!(
+            "\x1b[1;32m✓ No feature flapping:\x1b[0m no crate has more than {threshold} builds in a profile"
+        );
+        println!();
+        return;
+    }
+    println!(
+        "\x1b[1;33m🔀 Feature flapping\x1b[0m \x1b[2m({} crates with more than {threshold} builds side by side):\x1b[0m",
+        crates.len()
+    );
+    for krate in crates {
+        let total: u64 = krate.builds.iter().map(|b| b.bytes).sum();
+        println!(
+            "  {}{}\x1b[0m  \x1b[1;36m{}\x1b[0m  {} builds  \x1b[2m{}\x1b[0m",
+            crate::theme::profile_color(&krate.profile),
+            krate.profile,
+            krate.name,
+            krate.builds.len(),
+            format_bytes(total)
+        );
+        for build in &krate.builds {
+            let mut sources: Vec<String> =
+                build.commands.iter().map(|c| format!("`{c}`")).collect();
+            if !build.users.is_empty() {
+                sources.push(format!(
+                    "used by {}",
+                    build.users.iter().cloned().collect::<Vec<_>>().join(", ")
+                ));
+            }
+            let sources = if sources.is_empty() {
+                "\x1b[2mno stored trace uses it\x1b[0m".to_string()
+            } else {
+                sources.join("; ")
+            };
+            println!(
+                "      \x1b[38;5;242m{}\x1b[0m  {:>10}  {sources}",
+                build.hash,
+                format_bytes(build.bytes)
+            );
+        }
+    }
+    println!();
+    println!("\x1b[1mSuggestions:\x1b[0m");
+    println!(
+        "  • Enable the same features of these crates in every member, e.g. through `[workspace.dependencies]`."
+    );
+    if resolver_v1 {
+        println!(
+            "  • Set `resolver = \"2\"` in the workspace so dev- and build-dependency features stop leaking into normal builds."
+        );
+    }
+    println!(
+        "  • A workspace-hack crate (e.g. managed by cargo-hakari) unifies features so every build shares one configuration."
+    );
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn reports_crates_over_threshold_with_attribution() {
+        let tmp = tempfile::tempdir().unwrap();
+        let deps = tmp.path().join("deps");
+        fs::create_dir(&deps).unwrap();
+        for file in [
+            "libserde-aaaa.rlib",
+            "libserde-aaaa.rmeta",
+            "serde-aaaa.d",
+            "libserde-bbbb.rlib",
+            "libserde-cccc.rlib",
+            "libsyn-1111.rlib",
+            "libsyn-2222.rlib",
+        ] {
+            fs::write(deps.join(file), b"x").unwrap();
+        }
+        let mut used_by: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+        used_by
+            .entry(deps.join("libserde-aaaa.rlib"))
+            .or_default()
+            .insert("app".to_string());
+        let manifests = [TraceManifest {
+            command: "cargo build -p cli".to_string(),
+            recorded_at: 0,
+            artifacts: vec![
+                deps.join("libserde-bbbb.rmeta"),
+                deps.join("libserde-bbbb.rlib"),
+            ],
+            ..Default::default()
+        }];
+        let sizes = SizeCache::scan([deps.clone()]).await.unwrap();
+
+        let flapping = find_flapping(
+            &deps,
+            "debug",
+            &used_by,
+            &manifests,
+            &sizes,
+            SizeMode::Apparent,
+            2,
+        );
+        assert_eq!(flapping.len(), 1);
+        let serde = &flapping[0];
+        assert_eq!(serde.name, "serde");
+        let hashes: Vec<&str> = serde.builds.iter().map(|b| b.hash.as_str()).collect();
+        assert_eq!(hashes, ["aaaa", "bbbb", "cccc"]);
+        assert_eq!(serde.builds[0].bytes, 3);
+        assert!(serde.builds[0].users.contains("app"));
+        assert!(serde.builds[1].commands.contains("cargo build -p cli"));
+        assert!(serde.builds[2].users.is_empty() && serde.builds[2].commands.is_empty());
+    }
+
+    #[test]
+    fn detects_resolver_v1_workspaces() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manifest = tmp.path().join("Cargo.toml");
+        let check = |text: &str| {
+            fs::write(&manifest, text).unwrap();
+            uses_resolver_v1(&manifest)
+        };
+        assert!(check("[package]\nname = \"a\"\nedition = \"2018\"\n"));
+        assert!(!check("[package]\nname = \"a\"\nedition = \"2021\"\n"));
+        assert!(!check("[workspace]\nresolver = \"2\"\nmembers = []\n"));
+        assert!(check("[workspace]\nmembers = []\n"));
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use cargo_metadata::Metadata;
+use tokio::fs;
+
+use super::CleanCommand;
+use super::dep_graph::DepGraph;
+use super::display::print_build_profiles;
+use super::scan::{
+    SizeCache, artifact_stem, discover_deps_dirs, is_debug_bundle, output_crate_names,
+};
+use super::stats::{CleanupStats, RemovalKind};
+use super::unclassified::UnclassifiedRules;
+use super::units::{self, UnitIndex};
+use crate::crate_deps::crate_key;
+
+/// Stems of one crate built within this long of its newest stem are treated
+/// as the same build (e.g. a crate compiled with two feature sets, or for
+/// both the host and the target) and kept together.
+const SAME_BUILD_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// What the heuristic decided for one `deps/` directory.
+#[derive(Debug, Default)]
+struct HeuristicPlan {
+    keep: Vec<PathBuf>,
+    remove: Vec<PathBuf>,
+    /// Crates with artifacts on disk that are no longer in the resolve.
+    unlocked: BTreeSet<String>,
+}
+
+/// Crate names (as they appear in artifact file names) of every package in
+/// the current resolve, including each package's lib/bin/test targets, with
+/// the versions of the resolve that build them.
+fn current_crate_names(metadata: &Metadata) -> HashMap<String, BTreeSet<String>> {
+    let mut names: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for package in &metadata.packages {
+        let targets = package.targets.iter().map(|t| &t.name);
+        for name in std::iter::once(&package.name).chain(targets) {
+            names
+                .entry(name.replace('-', "_"))
+                .or_default()
+                .insert(package.version.to_string());
+        }
+    }
+    names
+}
+
+/// Group `files` by crate and version, then by stem.  Crates and versions
+/// missing from `current` are removed outright; for the rest, the newest stem
+/// set of each version is kept and older stems are removed.  Stems of an
+/// unknown version count as the locked one, or are all kept when the resolve
+/// has several versions of the crate, as they can't be told apart (`syn` 1.x
+/// and 2.x).
+fn plan_deps_dir(
+    files: Vec<(PathBuf, SystemTime)>,
+    current: &HashMap<String, BTreeSet<String>>,
+    version_of: impl Fn(&Path) -> Option<String>,
+) -> HeuristicPlan {
+    // (crate, version) -> stem -> (newest mtime, files)
+    type Stems = HashMap<String, (SystemTime, Vec<PathBuf>)>;
+    let mut by_crate: HashMap<(String, Option<String>), Stems> = HashMap::new();
+    for (path, mtime) in files {
+        let Some(stem) = artifact_stem(&path) else {
+            continue;
+        };
+        let krate = crate_key(&path);
+        let version = version_of(&path).or_else(|| match current.get(&krate) {
+            Some(versions) if versions.len() == 1 => versions.first().cloned(),
+            _ => None,
+        });
+        let entry = by_crate
+            .entry((krate, version))
+            .or_default()
+            .entry(stem)
+            .or_insert((SystemTime::UNIX_EPOCH, Vec::new()));
+        entry.0 = entry.0.max(mtime);
+        entry.1.push(path);
+    }
+
+    let mut plan = HeuristicPlan::default();
+    for ((krate, version), stems) in by_crate {
+        let Some(versions) = current.get(&krate) else {
+            plan.unlocked.insert(krate);
+            plan.remove
+                .extend(stems.into_values().flat_map(|(_, files)| files));
+            continue;
+        };
+        match version {
+            // Built before a `cargo update` or from an old lockfile
+            Some(version) if !versions.contains(&version) => {
+                plan.unlocked.insert(format!("{krate} {version}"));
+                plan.remove
+                    .extend(stems.into_values().flat_map(|(_, files)| files));
+                continue;
+            }
+            None => {
+                plan.keep
+                    .extend(stems.into_values().flat_map(|(_, files)| files));
+                continue;
+            }
+            Some(_) => {}
+        }
+        let newest = stems
+            .values()
+            .map(|(mtime, _)| *mtime)
+            .max()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        for (mtime, files) in stems.into_values() {
+            let same_build = newest
+                .duration_since(mtime)
+                .is_ok_and(|age| age <= SAME_BUILD_WINDOW);
+            if same_build {
+                plan.keep.extend(files);
+            } else {
+                plan.remove.extend(files);
+            }
+        }
+    }
+    plan
+}
+
+impl CleanCommand {
+    /// Plan a cleanup from the cargo resolve and file mtimes alone, without
+    /// running a build.  The result is flagged as an estimate.
+    pub(super) async fn remove_unused_files_without_trace(
+        &self,
+        metadata: &Metadata,
+        build_dir: &Path,
+    ) -> Result<CleanupStats> {
+        let deps_dirs = discover_deps_dirs(build_dir);
+        print_build_profiles(&deps_dirs);
+
+        let sizes = SizeCache::scan(
+            deps_dirs
+                .iter()
+                .filter_map(|(d, _)| d.parent())
+                .map(Path::to_path_buf),
+        )
+        .await?;
+        let current = current_crate_names(metadata);
+        // Nothing is traced, so no unclassified file can be tied to a unit
+        let no_outputs = HashSet::new();
+        let rules = UnclassifiedRules::new([], &no_outputs, &sizes);
+        log::debug!("Crates in the current resolve: {}", current.len());
+        // Versions of the units, to keep two locked versions of a crate apart
+        let graph = DepGraph::load(
+            deps_dirs.iter().map(|(d, _)| d.as_path()),
+            metadata.workspace_root.as_std_path(),
+        );
+        let packages = units::package_dirs(metadata);
+
+        let mut stats = CleanupStats {
+            estimated: true,
+            ..Default::default()
+        };
+        for (deps_dir, profile) in &deps_dirs {
+            let mut profile_stats = CleanupStats::default();
+            let mut files = Vec::new();
+            let mut entries = fs::read_dir(deps_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let Ok(meta) = entry.metadata().await else {
+                    continue;
+                };
+                if meta.is_file() && artifact_stem(&entry.path()).is_none() {
+                    let size = sizes.size_of(&entry.path()).get(self.size_mode());
+                    profile_stats.record_unclassified(&rules, entry.path(), profile, size);
+                } else if meta.is_file() || (meta.is_dir() && is_debug_bundle(&entry.path())) {
+                    let mtime = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    files.push((entry.path(), mtime));
+                }
+            }
+            let index = deps_dir
+                .parent()
+                .map(|profile_dir| UnitIndex::load(profile_dir, profile, &graph, &packages))
+                .unwrap_or_default();
+            let plan = plan_deps_dir(files, &current, |path| index.get(path)?.version.clone());
+            let kept: HashSet<PathBuf> = plan.keep.iter().cloned().collect();
+
+            for path in &plan.keep {
+                let sz = sizes.size_of(path).get(self.size_mode());
+                profile_stats.used_bytes += sz;
+                profile_stats
+                    .per_profile
+                    .entry(profile.clone())
+                    .or_default()
+                    .used_bytes += sz;
+            }
+            let (bundles, files): (Vec<PathBuf>, Vec<PathBuf>) =
+                plan.remove.into_iter().partition(|p| is_debug_bundle(p));
+            let to_remove = files
+                .into_iter()
+                .map(|p| {
+                    let file_sizes = sizes.size_of(&p);
+                    (p, file_sizes)
+                })
+                .collect();
+            Self::tally_files(
+                &mut profile_stats,
+                to_remove,
+                profile,
+                RemovalKind::Artifact,
+                &sizes,
+                self.size_mode(),
+            );
+            Self::tally_debug_bundles(
+                &mut profile_stats,
+                bundles,
+                profile,
+                &sizes,
+                self.size_mode(),
+            );
+            profile_stats.unlocked_crates = plan.unlocked;
+
+            if let Some(profile_dir) = deps_dir.parent() {
+                profile_stats
+                    .per_profile
+                    .entry(profile.clone())
+                    .or_default()
+                    .total_dir_bytes = sizes.size_of(profile_dir).get(self.size_mode());
+                let mut outputs = output_crate_names(profile_dir);
+                outputs.extend(output_crate_names(
+                    &metadata.target_directory.as_std_path().join(profile),
+                ));
+                match Self::clean_incremental_dir(
+                    profile_dir,
+                    &kept,
+                    &outputs,
+                    profile,
+                    &sizes,
+                    self.size_mode(),
+                )
+                .await
+                {
+                    Ok(inc_stats) => profile_stats.merge_from(inc_stats),
+                    Err(e) => log::warn!("Failed to scan incremental dir: {e}"),
+                }
+            }
+
+            stats.merge_from(profile_stats);
+        }
+
+        if self.stale_toolchains {
+            self.split_other_toolchains(&mut stats, build_dir, &deps_dirs, &sizes)
+                .await?;
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> HashMap<String, BTreeSet<String>> {
+        list.iter()
+            .map(|s| (s.to_string(), BTreeSet::from(["1.0.0".to_string()])))
+            .collect()
+    }
+
+    fn unknown(_: &Path) -> Option<String> {
+        None
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn sorted(mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn keeps_newest_stem_and_drops_older_builds() {
+        let day = 24 * 60 * 60;
+        let files = vec![
+            (PathBuf::from("libserde-new.rlib"), at(10 * day)),
+            (PathBuf::from("libserde-new.rmeta"), at(10 * day)),
+            (PathBuf::from("serde-new.d"), at(10 * day)),
+            (PathBuf::from("libserde-old.rlib"), at(day)),
+            (PathBuf::from("serde-old.d"), at(day)),
+        ];
+
+        let plan = plan_deps_dir(files, &names(&["serde"]), unknown);
+
+        assert_eq!(
+            sorted(plan.keep),
+            vec![
+                PathBuf::from("libserde-new.rlib"),
+                PathBuf::from("libserde-new.rmeta"),
+                PathBuf::from("serde-new.d"),
+            ]
+        );
+        assert_eq!(
+            sorted(plan.remove),
+            vec![
+                PathBuf::from("libserde-old.rlib"),
+                PathBuf::from("serde-old.d"),
+            ]
+        );
+        assert!(plan.unlocked.is_empty());
+    }
+
+    #[test]
+    fn stems_from_the_same_build_are_kept_together() {
+        // e.g. the same crate with two feature sets, built minutes apart
+        let files = vec![
+            (PathBuf::from("libsyn-a.rlib"), at(1_000)),
+            (PathBuf::from("libsyn-b.rlib"), at(1_300)),
+        ];
+
+        let plan = plan_deps_dir(files, &names(&["syn"]), unknown);
+
+        assert_eq!(plan.keep.len(), 2);
+        assert!(plan.remove.is_empty());
+    }
+
+    #[test]
+    fn crates_missing_from_the_resolve_are_removed() {
+        let files = vec![
+            (PathBuf::from("libgone-1.rlib"), at(5_000)),
+            (PathBuf::from("gone-1.d"), at(5_000)),
+            (PathBuf::from("libkept-2.rlib"), at(10)),
+            (PathBuf::from("no_hash.txt"), at(10)),
+        ];
+
+        let plan = plan_deps_dir(files, &names(&["kept"]), unknown);
+
+        assert_eq!(plan.keep, vec![PathBuf::from("libkept-2.rlib")]);
+        assert_eq!(plan.remove.len(), 2);
+        assert_eq!(plan.unlocked, BTreeSet::from(["gone".to_string()]));
+    }
+
+    #[test]
+    fn locked_versions_of_a_crate_are_planned_apart() {
+        let day = 24 * 60 * 60;
+        let files = vec![
+            (PathBuf::from("libsyn-one.rlib"), at(day)),
+            (PathBuf::from("libsyn-two.rlib"), at(10 * day)),
+            (PathBuf::from("libsyn-old2.rlib"), at(2 * day)),
+        ];
+        let version = |path: &Path| {
+            let stem = artifact_stem(path)?;
+            Some(
+                if stem.ends_with("one") {
+                    "1.0.109"
+                } else {
+                    "2.0.48"
+                }
+                .to_string(),
+            )
+        };
+        let current = HashMap::from([(
+            "syn".to_string(),
+            BTreeSet::from(["1.0.109".to_string(), "2.0.48".to_string()]),
+        )]);
+
+        let plan = plan_deps_dir(files.clone(), &current, version);
+        assert_eq!(
+            sorted(plan.keep),
+            [
+                PathBuf::from("libsyn-one.rlib"),
+                PathBuf::from("libsyn-two.rlib")
+            ]
+        );
+        assert_eq!(plan.remove, [PathBuf::from("libsyn-old2.rlib")]);
+
+        // Without versions, nothing can be told apart
+        let plan = plan_deps_dir(files, &current, unknown);
+        assert_eq!(plan.keep.len(), 3);
+    }
+
+    #[test]
+    fn versions_dropped_from_the_lockfile_are_removed() {
+        let day = 24 * 60 * 60;
+        let files = vec![
+            (PathBuf::from("libserde-old.rlib"), at(10 * day)),
+            (PathBuf::from("libserde-new.rlib"), at(day)),
+            // Its version is unknown: the locked one, and older
+            (PathBuf::from("libserde-anon.rlib"), at(0)),
+        ];
+        let version = |path: &Path| match artifact_stem(path)?.as_str() {
+            "serde-old" => Some("1.0.1".to_string()),
+            "serde-new" => Some("1.0.2".to_string()),
+            _ => None,
+        };
+        let current = HashMap::from([("serde".to_string(), BTreeSet::from(["1.0.2".to_string()]))]);
+
+        let plan = plan_deps_dir(files, &current, version);
+        assert_eq!(plan.keep, [PathBuf::from("libserde-new.rlib")]);
+        assert_eq!(
+            sorted(plan.remove),
+            [
+                PathBuf::from("libserde-anon.rlib"),
+                PathBuf::from("libserde-old.rlib")
+            ]
+        );
+        assert_eq!(plan.unlocked, BTreeSet::from(["serde 1.0.1".to_string()]));
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueHint};
+
+use crate::crate_deps::{format_bytes, format_utc};
+
+use super::ledger::{self, LedgerRecord};
+
+const WEEK_SECS: u64 = 7 * 24 * 60 * 60;
+/// Width of the trend bars, in characters.
+const BAR_WIDTH: u64 = 30;
+
+/// Show what past cleans reclaimed, from the run ledger.
+#[derive(Debug, Clone, Args)]
+pub(crate) struct StatsCommand {
+    /// Only include runs in this workspace directory.
+    #[clap(long, value_hint = ValueHint::DirPath, value_name = "DIR")]
+    workspace: Option<PathBuf>,
+
+    /// Weeks of history shown in the trend.
+    #[clap(long, value_name = "N", default_value = "8")]
+    weeks: u64,
+
+    /// Number of most frequently re-built crates to list.
+    #[clap(long, value_name = "N", default_value = "10")]
+    top: usize,
+
+    /// Ledger to read (defaults to $CARGO_CLEAN_ARTIFACT_LEDGER, then
+    /// ~/.local/share/cargo-clean-artifact/ledger.jsonl).
+    #[clap(long, value_hint = ValueHint::FilePath, value_name = "FILE")]
+    ledger: Option<PathBuf>,
+}
+
+/// One week of the trend.
+#[derive(Debug, Default, PartialEq)]
+struct WeekStat {
+    /// Monday 00:00 UTC, in seconds since the Unix epoch
+    start: u64,
+    reclaimed: u64,
+    runs: usize,
+}
+
+/// Start (Monday 00:00 UTC) of the week containing `secs`; the epoch for the
+/// days before the first Monday (a ledger line without a timestamp).
+fn week_start(secs: u64) -> u64 {
+    let days = secs / 86_400;
+    // 1970-01-01 was a Thursday
+    ((days + 3) / 7 * 7).saturating_sub(3) * 86_400
+}
+
+/// Reclaimed bytes per week for the `weeks` weeks up to and including `now`.
+fn weekly_trend(records: &[LedgerRecord], now: u64, weeks: u64) -> Vec<WeekStat> {
+    let current = week_start(now);
+    // No more weeks than there are Mondays since the epoch
+    let weeks = weeks.min(current / WEEK_SECS + 1);
+    let first = current.saturating_sub(weeks.saturating_sub(1) * WEEK_SECS);
+    let mut trend: Vec<WeekStat> = (0..weeks)
+        .map(|i| first + i * WEEK_SECS)
+        .filter(|start| *start <= current)
+        .map(|start| WeekStat {
+            start,
+            ..Default::default()
+        })
+        .collect();
+    for record in records {
+        let start = week_start(record.timestamp);
+        if let Some(week) = trend.iter_mut().find(|w| w.start == start) {
+            week.runs += 1;
+            week.reclaimed += record.removed_bytes;
+        }
+    }
+    trend
+}
+
+/// `(crate, cleans that removed stale builds of it, bytes)`, most frequent
+/// first.  Dry-runs are left out: they find the same builds again and again.
+fn frequent_crates(records: &[LedgerRecord], top: usize) -> Vec<(String, usize, u64)> {
+    let mut by_crate: HashMap<&str, (usize, u64)> = HashMap::new();
+    for record in records.iter().filter(|r| !r.dry_run) {
+        for (name, bytes) in &record.per_crate {
+            let entry = by_crate.entry(name).or_default();
+            entry.0 += 1;
+            entry.1 += bytes;
+        }
+    }
+    let mut crates: Vec<(String, usize, u64)> = by_crate
+        .into_iter()
+        .map(|(name, (runs, bytes))| (name.to_string(), runs, bytes))
+        .collect();
+    crates.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+    crates.truncate(top);
+    crates
+}
+
+impl StatsCommand {
+    pub(crate) async fn run(self) -> Result<()> {
+        let path = match self.ledger.clone() {
+            Some(path) => path,
+            None => ledger::default_path().context("cannot locate the ledger: pass --ledger")?,
+        };
+        let mut records = ledger::load(&path)?;
+        if let Some(workspace) = &self.workspace {
+            let workspace = std::path::absolute(workspace)?;
+            records.retain(|r| r.workspace == workspace);
+        }
+
+        println!("📒 Ledger: \x1b[1m{}\x1b[0m", path.display());
+        if records.is_empty() {
+            println!("   No runs recorded yet.");
+            return Ok(());
+        }
+
+        let workspaces: HashSet<&PathBuf> = records.iter().map(|r| &r.workspace).collect();
+        let (cleans, dry_runs): (Vec<&LedgerRecord>, Vec<&LedgerRecord>) =
+            records.iter().partition(|r| !r.dry_run);
+        let first = records.iter().map(|r| r.timestamp).min().unwrap_or(0);
+        println!(
+            "   {} runs in {} workspaces since {}",
+            records.len(),
+            workspaces.len(),
+            &format_utc(UNIX_EPOCH + Duration::from_secs(first))[..10]
+        );
+        println!();
+        println!(
+            "\x1b[1;32m✓ Reclaimed {}\x1b[0m in {} cleans  \x1b[2m•  dry-runs found {} more\x1b[0m",
+            format_bytes(cleans.iter().map(|r| r.removed_bytes).sum()),
+            cleans.len(),
+            format_bytes(dry_runs.iter().map(|r| r.reclaimable_bytes).sum()),
+        );
+        println!();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let trend = weekly_trend(&records, now, self.weeks);
+        let max = trend.iter().map(|w| w.reclaimed).max().unwrap_or(0).max(1);
+        println!("\x1b[1mWeekly reclaimed:\x1b[0m");
+        for week in &trend {
+            let bar = "█".repeat((week.reclaimed * BAR_WIDTH / max) as usize);
+            println!(
+                "  {}  \x1b[36m{bar:<width$}\x1b[0m {:>10} \x1b[2m({} runs)\x1b[0m",
+                &format_utc(UNIX_EPOCH + Duration::from_secs(week.start))[..10],
+                format_bytes(week.reclaimed),
+                week.runs,
+                width = BAR_WIDTH as usize,
+            );
+        }
+        println!();
+
+        let crates = frequent_crates(&records, self.top);
+        if !crates.is_empty() {
+            println!("\x1b[1mMost frequently re-built crates:\x1b[0m");
+            let name_width = crates.iter().map(|c| c.0.len()).max().unwrap_or(0);
+            for (i, (name, runs, bytes)) in crates.iter().enumerate() {
+                println!(
+                    "  {:>2}. {name:<name_width$}  {runs} cleans  \x1b[2m{}\x1b[0m",
+                    i + 1,
+                    format_bytes(*bytes)
+                );
+            }
+        }
+
+        // Per-workspace totals help spot the project that churns the most
+        if workspaces.len() > 1 {
+            let mut per_workspace: BTreeMap<&PathBuf, u64> = BTreeMap::new();
+            for record in &cleans {
+                *per_workspace.entry(&record.workspace).or_default() += record.removed_bytes;
+            }
+            println!();
+            println!("\x1b[1mReclaimed by workspace:\x1b[0m");
+            for (workspace, bytes) in per_workspace {
+                println!("  {}  {}", workspace.display(), format_bytes(bytes));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: u64, removed_bytes: u64, crates: &[&str]) -> LedgerRecord {
+        LedgerRecord {
+            timestamp,
+            removed_bytes,
+            per_crate: crates.iter().map(|c| (c.to_string(), 10)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // 2024-02-29 (Thursday) → 2024-02-26 (Monday)
+        assert_eq!(week_start(1_709_251_199), 1_708_905_600);
+        assert_eq!(week_start(1_708_905_600), 1_708_905_600);
+        // The first days of 1970 precede any Monday
+        assert_eq!(week_start(0), 0);
+        assert_eq!(week_start(2 * 86_400 + 1), 0);
+        assert_eq!(week_start(4 * 86_400), 4 * 86_400);
+    }
+
+    #[test]
+    fn trend_buckets_runs_by_week() {
+        let now = 1_709_251_199;
+        let records = [
+            record(now - 60, 100, &[]),
+            record(now - WEEK_SECS, 50, &[]),
+            record(now - WEEK_SECS, 25, &[]),
+            record(now - 20 * WEEK_SECS, 999, &[]),
+        ];
+
+        let trend = weekly_trend(&records, now, 3);
+        let reclaimed: Vec<(u64, usize)> = trend.iter().map(|w| (w.reclaimed, w.runs)).collect();
+        assert_eq!(reclaimed, vec![(0, 0), (75, 2), (100, 1)]);
+
+        // More weeks than the epoch has: from the first one on
+        let trend = weekly_trend(&records, now, u64::MAX);
+        // 1970-01-05, the first Monday
+        assert_eq!(trend.first().map(|w| w.start), Some(4 * 86_400));
+        assert_eq!(trend.last().map(|w| w.start), Some(week_start(now)));
+    }
+
+    #[test]
+    fn frequent_crates_are_ranked_by_run_count() {
+        let records = [
+            record(0, 0, &["serde", "syn"]),
+            record(0, 0, &["syn"]),
+            record(0, 0, &["syn", "tokio"]),
+            LedgerRecord {
+                dry_run: true,
+                ..record(0, 0, &["serde", "tokio"])
+            },
+        ];
+        let crates = frequent_crates(&records, 2);
+        assert_eq!(
+            crates,
+            vec![("syn".to_string(), 3, 30), ("serde".to_string(), 1, 10)]
+        );
+    }
+}
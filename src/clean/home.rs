@@ -0,0 +1,392 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use cargo_metadata::MetadataCommand;
+use clap::{ArgAction, Args, ValueHint};
+use tokio::fs;
+
+use crate::lockfile::{find_lockfiles, git_commit, is_registry_source, parse_lockfile};
+
+use super::display::print_profile_breakdown;
+use super::scan::{SizeCache, SizeMode};
+use super::stats::{CleanupStats, DirToRemove, FileToRemove, RemovalKind};
+
+/// Clean the cargo home (`$CARGO_HOME`, usually `~/.cargo`).
+///
+/// Every `Cargo.lock` found below DIR is collected, and any extracted
+/// registry source, downloaded `.crate` archive or git checkout that none of
+/// them references is offered for removal.
+#[derive(Debug, Clone, Args)]
+pub(crate) struct HomeCommand {
+    /// Actually remove files (dry-run is the default).
+    #[clap(short = 'y', long = "yes")]
+    yes: bool,
+
+    /// Force dry-run mode (default behavior).
+    #[clap(long, action = ArgAction::SetTrue)]
+    dry_run: bool,
+
+    /// Directories searched recursively for workspaces whose dependencies must be kept.
+    #[clap(
+        value_hint = ValueHint::DirPath,
+        default_value = ".",
+        value_name = "DIR"
+    )]
+    dirs: Vec<PathBuf>,
+
+    /// Cargo home to clean (defaults to $CARGO_HOME, then ~/.cargo).
+    #[clap(long, value_hint = ValueHint::DirPath, value_name = "PATH")]
+    cargo_home: Option<PathBuf>,
+
+    /// Measure sizes by logical file length instead of allocated disk blocks.
+    #[clap(long, action = ArgAction::SetTrue)]
+    apparent_size: bool,
+}
+
+/// Crate versions and git revisions referenced by the known workspaces.
+#[derive(Debug, Default)]
+struct References {
+    /// `(name, version)` of registry packages
+    registry: HashSet<(String, String)>,
+    /// Full commit hashes of git packages
+    git_commits: HashSet<String>,
+    workspaces: usize,
+}
+
+impl References {
+    fn add(&mut self, name: &str, version: &str, source: Option<&str>) {
+        let Some(source) = source else { return };
+        if is_registry_source(source) {
+            self.registry
+                .insert((name.to_string(), version.to_string()));
+        } else if let Some(commit) = git_commit(source) {
+            self.git_commits.insert(commit.to_string());
+        }
+    }
+
+    /// Whether `<name>-<version>` (a `.crate` stem or a `registry/src` dir
+    /// name) is referenced.  Crate names may contain `-`, so every split
+    /// point in front of a digit is tried.
+    fn has_registry(&self, name_version: &str) -> bool {
+        name_version.match_indices('-').any(|(i, _)| {
+            let (name, version) = (&name_version[..i], &name_version[i + 1..]);
+            version.starts_with(|c: char| c.is_ascii_digit())
+                && self
+                    .registry
+                    .contains(&(name.to_string(), version.to_string()))
+        })
+    }
+
+    /// Whether a checkout directory (named by an abbreviated commit) is referenced.
+    fn has_checkout(&self, short_rev: &str) -> bool {
+        self.git_commits.iter().any(|c| c.starts_with(short_rev))
+    }
+}
+
+impl HomeCommand {
+    fn size_mode(&self) -> SizeMode {
+        if self.apparent_size {
+            SizeMode::Apparent
+        } else {
+            SizeMode::DiskUsage
+        }
+    }
+
+    fn cargo_home(&self) -> Result<PathBuf> {
+        if let Some(home) = &self.cargo_home {
+            return Ok(home.clone());
+        }
+        if let Some(home) = std::env::var_os("CARGO_HOME") {
+            return Ok(PathBuf::from(home));
+        }
+        std::env::home_dir()
+            .map(|h| h.join(".cargo"))
+            .context("cannot locate the cargo home; pass --cargo-home")
+    }
+
+    /// Collect references from every `Cargo.lock` below the given dirs, plus
+    /// the `cargo metadata` resolve of each dir that is itself a workspace.
+    fn collect_references(&self) -> Result<References> {
+        let mut refs = References::default();
+        for dir in &self.dirs {
+            for lockfile in find_lockfiles(dir) {
+                log::debug!("Reading {}", lockfile.display());
+                match parse_lockfile(&lockfile) {
+                    Ok(packages) => {
+                        refs.workspaces += 1;
+                        for p in &packages {
+                            refs.add(&p.name, &p.version, p.source.as_deref());
+                        }
+                    }
+                    Err(e) => log::warn!("Skipping unreadable lockfile: {e:#}"),
+                }
+            }
+
+            if dir.join("Cargo.toml").is_file() {
+                match MetadataCommand::new()
+                    .current_dir(dir)
+                    .other_options(vec!["--offline".to_string()])
+                    .exec()
+                {
+                    Ok(metadata) => {
+                        for p in &metadata.packages {
+                            let source = p.source.as_ref().map(|s| s.repr.as_str());
+                            refs.add(&p.name, &p.version.to_string(), source);
+                        }
+                    }
+                    Err(e) => log::debug!("Metadata error in {}: {e}", dir.display()),
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    async fn plan(&self, cargo_home: &Path, refs: &References) -> Result<CleanupStats> {
+        let registry_cache = cargo_home.join("registry/cache");
+        let registry_src = cargo_home.join("registry/src");
+        let checkouts = cargo_home.join("git/checkouts");
+
+        let sizes = SizeCache::scan(
+            [&registry_cache, &registry_src, &checkouts]
+                .into_iter()
+                .filter(|p| p.exists())
+                .cloned(),
+        )
+        .await?;
+        let mode = self.size_mode();
+        let mut stats = CleanupStats::default();
+
+        // registry/cache/<index>/<name>-<version>.crate
+        for index_dir in subdirs(&registry_cache).await? {
+            let profile = index_label(&index_dir);
+            let mut entries = fs::read_dir(&index_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let Some(stem) = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| n.strip_suffix(".crate"))
+                else {
+                    continue;
+                };
+                if refs.has_registry(stem) {
+                    continue;
+                }
+                let file_sizes = sizes.size_of(&path);
+                let size = file_sizes.get(mode);
+                stats.add_reclaimable(&profile, size, file_sizes);
+                let crate_entry = stats.per_crate.entry(stem.to_string()).or_default();
+                crate_entry.files += 1;
+                crate_entry.bytes += size;
+                stats.files_to_remove.push(FileToRemove {
+                    path,
+                    size,
+                    sizes: file_sizes,
+                    profile: profile.clone(),
+                    kind: RemovalKind::RegistryCache,
+                    unit: None,
+                });
+            }
+        }
+
+        // registry/src/<index>/<name>-<version>/
+        for index_dir in subdirs(&registry_src).await? {
+            let profile = index_label(&index_dir);
+            for src_dir in subdirs(&index_dir).await? {
+                let name = dir_name(&src_dir);
+                if refs.has_registry(&name) {
+                    continue;
+                }
+                push_dir(&mut stats, &sizes, mode, src_dir, &profile, name);
+            }
+        }
+
+        // git/checkouts/<repo>-<hash>/<short-rev>/ — drop the whole repo dir when
+        // none of its revisions is referenced any more.
+        for repo_dir in subdirs(&checkouts).await? {
+            let revs = subdirs(&repo_dir).await?;
+            let stale: Vec<PathBuf> = revs
+                .iter()
+                .filter(|rev| !refs.has_checkout(&dir_name(rev)))
+                .cloned()
+                .collect();
+            let repo = dir_name(&repo_dir);
+            if !revs.is_empty() && stale.len() == revs.len() {
+                push_dir(&mut stats, &sizes, mode, repo_dir, "git", repo);
+            } else {
+                for rev in stale {
+                    push_dir(&mut stats, &sizes, mode, rev, "git", repo.clone());
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    pub(crate) async fn run(self) -> Result<()> {
+        let cargo_home = self.cargo_home()?;
+        log::debug!("Cargo home: {}", cargo_home.display());
+
+        let refs = self.collect_references()?;
+        if refs.workspaces == 0 {
+            anyhow::bail!(
+                "no Cargo.lock found under {}; refusing to treat every cached crate as unused",
+                self.dirs
+                    .iter()
+                    .map(|d| d.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        println!(
+            "🔎 Found \x1b[1;36m{}\x1b[0m workspaces referencing \x1b[1;36m{}\x1b[0m registry crates and \x1b[1;36m{}\x1b[0m git revisions",
+            refs.workspaces,
+            refs.registry.len(),
+            refs.git_commits.len(),
+        );
+        println!(
+            "\x1b[2m   Crates used only by projects outside these directories will be re-downloaded on their next build.\x1b[0m"
+        );
+        println!();
+
+        let stats = self.plan(&cargo_home, &refs).await?;
+
+        print_profile_breakdown(&stats);
+
+        if stats.files == 0 {
+            println!("✨ Nothing unreferenced in {}.", cargo_home.display());
+            return Ok(());
+        }
+
+        super::confirm_and_remove(&stats, self.yes, self.dry_run).await?;
+        Ok(())
+    }
+}
+
+fn push_dir(
+    stats: &mut CleanupStats,
+    sizes: &SizeCache,
+    mode: SizeMode,
+    path: PathBuf,
+    profile: &str,
+    crate_name: String,
+) {
+    let dir_sizes = sizes.size_of(&path);
+    let size = dir_sizes.get(mode);
+    let kind = if profile == "git" {
+        RemovalKind::GitCheckout
+    } else {
+        RemovalKind::RegistrySource
+    };
+    stats.add_reclaimable(profile, size, dir_sizes);
+    let crate_entry = stats.per_crate.entry(crate_name).or_default();
+    crate_entry.files += 1;
+    crate_entry.bytes += size;
+    stats.dirs_to_remove.push(DirToRemove {
+        path,
+        size,
+        sizes: dir_sizes,
+        profile: profile.to_string(),
+        kind,
+    });
+}
+
+async fn subdirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return Ok(dirs);
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn dir_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// `index.crates.io-1949cf8c6b5b557f` → `registry/index.crates.io`
+fn index_label(index_dir: &Path) -> String {
+    let name = dir_name(index_dir);
+    let short = name.rsplit_once('-').map_or(name.as_str(), |(s, _)| s);
+    format!("registry/{short}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn refs() -> References {
+        let mut refs = References::default();
+        refs.add(
+            "regex-automata",
+            "0.4.9",
+            Some("registry+https://github.com/rust-lang/crates.io-index"),
+        );
+        refs.add("local", "0.1.0", None);
+        refs.add(
+            "forked",
+            "0.2.0",
+            Some("git+https://github.com/me/forked#0123456789abcdef"),
+        );
+        refs.workspaces = 1;
+        refs
+    }
+
+    #[test]
+    fn registry_match_handles_dashes_in_names() {
+        let refs = refs();
+        assert!(refs.has_registry("regex-automata-0.4.9"));
+        assert!(!refs.has_registry("regex-automata-0.4.8"));
+        assert!(!refs.has_registry("local-0.1.0"));
+        assert!(refs.has_checkout("0123456"));
+        assert!(!refs.has_checkout("fedcba9"));
+    }
+
+    #[tokio::test]
+    async fn plan_marks_only_unreferenced_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path();
+        let cache = home.join("registry/cache/index.crates.io-abc");
+        let src = home.join("registry/src/index.crates.io-abc");
+        fs::create_dir_all(&cache).unwrap();
+        fs::create_dir_all(src.join("regex-automata-0.4.9")).unwrap();
+        fs::create_dir_all(src.join("serde-1.0.0")).unwrap();
+        fs::write(cache.join("regex-automata-0.4.9.crate"), b"keep").unwrap();
+        fs::write(cache.join("serde-1.0.0.crate"), b"drop").unwrap();
+        fs::create_dir_all(home.join("git/checkouts/forked-1111/0123456")).unwrap();
+        fs::create_dir_all(home.join("git/checkouts/forked-1111/aaaaaaa")).unwrap();
+        fs::create_dir_all(home.join("git/checkouts/gone-2222/bbbbbbb")).unwrap();
+
+        let cmd = HomeCommand {
+            yes: false,
+            dry_run: true,
+            dirs: vec![],
+            cargo_home: None,
+            apparent_size: false,
+        };
+        let stats = cmd.plan(home, &refs()).await.unwrap();
+
+        let files: Vec<_> = stats.files_to_remove.iter().map(|f| &f.path).collect();
+        assert_eq!(files, vec![&cache.join("serde-1.0.0.crate")]);
+
+        let mut dirs: Vec<_> = stats.dirs_to_remove.iter().map(|d| &d.path).collect();
+        dirs.sort();
+        assert_eq!(
+            dirs,
+            vec![
+                &home.join("git/checkouts/forked-1111/aaaaaaa"),
+                &home.join("git/checkouts/gone-2222"),
+                &src.join("serde-1.0.0"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use cargo_metadata::MetadataCommand;
+use clap::{ArgAction, Args, ValueHint};
+
+use super::branches::git;
+
+/// The hooks run after the working tree may have changed `Cargo.lock`.
+const HOOKS: &[&str] = &["post-checkout", "post-merge"];
+
+/// First comment line of every hook we write; how we recognise our own.
+const MARKER: &str = "# Installed by `cargo clean-artifact install-hook`";
+
+/// A hook that was there before ours is renamed to this and run first.
+const CHAINED_SUFFIX: &str = ".pre-clean-artifact";
+
+/// Install `post-checkout` and `post-merge` git hooks.
+///
+/// Whenever a checkout or merge changes `Cargo.lock`, the hooks run `watch
+/// --once` in the background with the stored traces: a dry-run report by
+/// default, or a clean with `--clean`. Existing hooks are kept and run first.
+#[derive(Debug, Clone, Args)]
+pub(crate) struct InstallHookCommand {
+    /// Clean with the stored traces instead of only reporting.
+    #[clap(long, action = ArgAction::SetTrue)]
+    clean: bool,
+
+    /// Workspace whose repository gets the hooks.
+    #[clap(value_hint = ValueHint::DirPath, default_value = ".", value_name = "DIR")]
+    dir: PathBuf,
+}
+
+/// Remove the hooks written by `install-hook`, restoring any hook they chained.
+#[derive(Debug, Clone, Args)]
+pub(crate) struct UninstallHookCommand {
+    /// Workspace whose repository has the hooks.
+    #[clap(value_hint = ValueHint::DirPath, default_value = ".", value_name = "DIR")]
+    dir: PathBuf,
+}
+
+/// Quote `text` for a POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// The script of `hook`, reacting to changes of `lockfile` (relative to the
+/// repository root) by reporting on or cleaning `target_dir`.
+fn hook_script(hook: &str, lockfile: &str, target_dir: &Path, clean: bool) -> String {
+    let lockfile = shell_quote(lockfile);
+    // `git diff --quiet` exits with 1 for a difference, and e.g. 128 for the
+    // null ref of the checkout done by `git clone`
+    let changed = match hook {
+        // $3 is 0 for a file checkout, which leaves HEAD alone
+        "post-checkout" => format!(
+            "[ \"$3\" = 1 ] && {{ git diff --quiet \"$1\" \"$2\" -- {lockfile} 2>/dev/null; [ $? = 1 ]; }}"
+        ),
+        _ => {
+            format!("{{ git diff --quiet ORIG_HEAD HEAD -- {lockfile} 2>/dev/null; [ $? = 1 ]; }}")
+        }
+    };
+    let (action, dry_run) = if clean {
+        ("cleaning", "")
+    } else {
+        ("reporting", " --dry-run")
+    };
+    let target = target_dir.display().to_string();
+    let log = target_dir.join(".clean-artifact/watch.log");
+    format!(
+        r#"#!/bin/sh
+{MARKER}; remove with `uninstall-hook`.
+status=0
+if [ -x "$0{CHAINED_SUFFIX}" ]; then
+    "$0{CHAINED_SUFFIX}" "$@" || status=$?
+fi
+if {changed} && command -v cargo-clean-artifact >/dev/null 2>&1; then
+    echo "cargo-clean-artifact: Cargo.lock changed, {action} in the background ({log})"
+    nohup cargo-clean-artifact watch --once --every 0s{dry_run} {target} >/dev/null 2>&1 &
+fi
+exit $status
+"#,
+        log = log.display(),
+        target = shell_quote(&target),
+    )
+}
+
+fn is_ours(path: &Path) -> bool {
+    std::fs::read_to_string(path).is_ok_and(|s| s.contains(MARKER))
+}
+
+fn chained(hook: &Path) -> PathBuf {
+    let mut name = hook.as_os_str().to_owned();
+    name.push(CHAINED_SUFFIX);
+    PathBuf::from(name)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .with_context(|| format!("making {} executable", path.display()))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Write `script` as `hooks_dir/name`, moving a foreign hook aside to be chained.
+fn install(hooks_dir: &Path, name: &str, script: &str) -> Result<()> {
+    let hook = hooks_dir.join(name);
+    if hook.exists() && !is_ours(&hook) {
+        let chained = chained(&hook);
+        if chained.exists() {
+            bail!(
+                "{} and {} both exist; remove one first",
+                hook.display(),
+                chained.display()
+            );
+        }
+        std::fs::rename(&hook, &chained)
+            .with_context(|| format!("moving {} aside", hook.display()))?;
+        println!("  {name}: existing hook kept as {}", chained.display());
+    }
+    std::fs::create_dir_all(hooks_dir)
+        .with_context(|| format!("creating {}", hooks_dir.display()))?;
+    std::fs::write(&hook, script).with_context(|| format!("writing {}", hook.display()))?;
+    make_executable(&hook)
+}
+
+/// Remove our `hooks_dir/name` and put back the hook it chained. Returns
+/// whether there was anything to remove.
+fn uninstall(hooks_dir: &Path, name: &str) -> Result<bool> {
+    let hook = hooks_dir.join(name);
+    if !is_ours(&hook) {
+        return Ok(false);
+    }
+    std::fs::remove_file(&hook).with_context(|| format!("removing {}", hook.display()))?;
+    println!("  {name}: removed");
+    let chained = chained(&hook);
+    if chained.exists() {
+        std::fs::rename(&chained, &hook)
+            .with_context(|| format!("restoring {}", hook.display()))?;
+        println!("  {name}: restored the previous hook");
+    }
+    Ok(true)
+}
+
+/// The hooks directory of the repository containing `dir` (honouring
+/// `core.hooksPath`) and the repository's top level.
+fn hooks_dir(dir: &Path) -> Result<(PathBuf, PathBuf)> {
+    let top = git(dir, &["rev-parse", "--show-toplevel"])
+        .with_context(|| format!("{} is not in a git repository", dir.display()))?;
+    let hooks = git(dir, &["rev-parse", "--git-path", "hooks"])
+        .context("failed to locate the git hooks directory")?;
+    let hooks = dir.join(hooks);
+    Ok((
+        std::path::absolute(&hooks).unwrap_or(hooks),
+        PathBuf::from(top),
+    ))
+}
+
+impl InstallHookCommand {
+    pub(crate) async fn run(self) -> Result<()> {
+        let metadata = MetadataCommand::new()
+            .current_dir(&self.dir)
+            .no_deps()
+            .exec()
+            .context("failed to read cargo metadata")?;
+        let (hooks_dir, top) = hooks_dir(&self.dir)?;
+        let workspace = metadata.workspace_root.as_std_path();
+        let top = top.canonicalize().unwrap_or(top);
+        let lockfile = workspace
+            .join("Cargo.lock")
+            .strip_prefix(&top)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "Cargo.lock".to_string());
+
+        println!(
+            "🪝 Installing hooks in \x1b[1;36m{}\x1b[0m",
+            hooks_dir.display()
+        );
+        for name in HOOKS {
+            let script = hook_script(
+                name,
+                &lockfile,
+                metadata.target_directory.as_std_path(),
+                self.clean,
+            );
+            install(&hooks_dir, name, &script)?;
+            println!("  {name}: installed");
+        }
+        println!(
+            "   When {lockfile} changes, {} in the background.",
+            if self.clean {
+                "target/ is cleaned with the stored traces"
+            } else {
+                "a dry-run report is written to the watch log"
+            }
+        );
+        Ok(())
+    }
+}
+
+impl UninstallHookCommand {
+    pub(crate) async fn run(self) -> Result<()> {
+        let (hooks_dir, _) = hooks_dir(&self.dir)?;
+        let mut removed = 0;
+        for name in HOOKS {
+            if uninstall(&hooks_dir, name)? {
+                removed += 1;
+            }
+        }
+        if removed == 0 {
+            println!(
+                "No hooks installed by cargo clean-artifact in {}",
+                hooks_dir.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn foreign_hooks_are_chained_and_restored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let hooks = tmp.path();
+        let foreign = "#!/bin/sh\necho lfs\n";
+        fs::write(hooks.join("post-merge"), foreign).unwrap();
+        let script = |hook| hook_script(hook, "Cargo.lock", Path::new("/w/target"), false);
+
+        for _ in 0..2 {
+            for name in HOOKS {
+                install(hooks, name, &script(name)).unwrap();
+            }
+        }
+        assert!(is_ours(&hooks.join("post-checkout")));
+        assert!(is_ours(&hooks.join("post-merge")));
+        assert_eq!(
+            fs::read_to_string(hooks.join("post-merge.pre-clean-artifact")).unwrap(),
+            foreign
+        );
+        assert!(!hooks.join("post-checkout.pre-clean-artifact").exists());
+
+        for name in HOOKS {
+            assert!(uninstall(hooks, name).unwrap());
+        }
+        assert!(!hooks.join("post-checkout").exists());
+        assert_eq!(
+            fs::read_to_string(hooks.join("post-merge")).unwrap(),
+            foreign
+        );
+        assert!(!uninstall(hooks, "post-merge").unwrap());
+    }
+
+    #[test]
+    fn script_reports_unless_cleaning() {
+        let report = hook_script("post-checkout", "app/Cargo.lock", Path::new("/w t"), false);
+        assert!(report.contains(r#"git diff --quiet "$1" "$2" -- 'app/Cargo.lock'"#));
+        assert!(report.contains("watch --once --every 0s --dry-run '/w t' "));
+        assert!(report.ends_with("exit $status\n"));
+        let clean = hook_script("post-merge", "Cargo.lock", Path::new("/w"), true);
+        assert!(clean.contains("ORIG_HEAD HEAD"));
+        assert!(!clean.contains("--dry-run"));
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use tokio::fs;
+
+use super::CleanCommand;
+use super::build_dir::unit_dirs;
+use super::scan::{SizeCache, SizeMode};
+use super::stats::{CleanupStats, DirToRemove, FileToRemove, RemovalKind};
+use crate::crate_deps::crate_key;
+
+/// A unit's artifact is written at the end of the session that compiled it;
+/// an artifact modified this long after the session started still counts as
+/// that session's output (a large crate can take minutes to compile).
+const SESSION_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// What an entry of `incremental/<unit>/` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionEntry {
+    /// `s-<time>-<id>-<hash>/`: a completed session
+    Finalized,
+    /// `s-<time>-<id>-working/`: in progress, or left behind by a crash
+    Working,
+    /// `s-<time>-<id>.lock`
+    Lock,
+}
+
+/// One entry of a unit directory, parsed from its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Session {
+    /// `s-<time>-<id>`, shared by a session dir and its lock file
+    id: String,
+    /// When rustc started the session
+    started: SystemTime,
+    entry: SessionEntry,
+}
+
+/// Parse a session dir or lock file name.  The time is base-36 microseconds
+/// since the Unix epoch.
+fn parse_session(name: &str) -> Option<Session> {
+    let (base, entry) = match name.strip_suffix(".lock") {
+        Some(base) => (base, SessionEntry::Lock),
+        None => {
+            let (base, suffix) = name.rsplit_once('-')?;
+            let entry = if suffix == "working" {
+                SessionEntry::Working
+            } else {
+                SessionEntry::Finalized
+            };
+            (base, entry)
+        }
+    };
+    let mut parts = base.splitn(3, '-');
+    let (Some("s"), Some(time), Some(_)) = (parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    let micros = u64::from_str_radix(time, 36).ok()?;
+    Some(Session {
+        id: base.to_string(),
+        started: UNIX_EPOCH + Duration::from_micros(micros),
+        entry,
+    })
+}
+
+/// The unit's crate name: `incremental/<crate>-<unit hash>`.
+fn unit_crate(unit_dir: &Path) -> Option<&str> {
+    let name = unit_dir.file_name()?.to_str()?;
+    Some(name.rsplit_once('-').map_or(name, |(krate, _)| krate))
+}
+
+/// Modification times of the used artifacts of `profile_dir`, and of the
+/// intermediates of the profile's final `outputs`, by crate.
+fn used_artifact_times(
+    profile_dir: &Path,
+    used_artifacts: &HashSet<PathBuf>,
+    outputs: &HashSet<String>,
+) -> HashMap<String, Vec<SystemTime>> {
+    // `deps/`, or the `out/` of every unit in the per-unit build-dir layout
+    let artifact_dirs = std::iter::once(profile_dir.join("deps"))
+        .chain(unit_dirs(profile_dir).into_iter().map(|u| u.join("out")));
+    let output_files = artifact_dirs
+        .flat_map(|dir| std::fs::read_dir(dir).into_iter().flatten())
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| outputs.contains(&crate_key(p)));
+    let used = used_artifacts
+        .iter()
+        .filter(|a| a.starts_with(profile_dir))
+        .cloned();
+
+    let mut times: HashMap<String, Vec<SystemTime>> = HashMap::new();
+    for artifact in used.chain(output_files) {
+        if let Ok(mtime) = std::fs::metadata(&artifact).and_then(|m| m.modified()) {
+            times.entry(crate_key(&artifact)).or_default().push(mtime);
+        }
+    }
+    times
+}
+
+/// Whether a used artifact of the unit's crate was written by the session
+/// that started at `started`.
+fn written_by_session(started: SystemTime, artifact_times: &[SystemTime]) -> bool {
+    // mtimes may be truncated to whole seconds
+    let from = started - Duration::from_secs(1);
+    artifact_times
+        .iter()
+        .any(|t| *t >= from && *t <= started + SESSION_WINDOW)
+}
+
+impl CleanCommand {
+    /// Scan `<profile_dir>/incremental/` and mark stale data for removal.
+    ///
+    /// Each `<crate>-<unit hash>/` directory belongs to one compilation unit
+    /// (a crate's lib, bin and test units each get their own) and holds one
+    /// `s-*` session per compilation.  A unit is in use when a used artifact
+    /// of its crate was written by its newest session; its older sessions and
+    /// leftover `-working` dirs and `.lock` files are removed.  Units no used
+    /// artifact ties to are removed whole.
+    pub(super) async fn clean_incremental_dir(
+        profile_dir: &Path,
+        used_artifacts: &HashSet<PathBuf>,
+        outputs: &HashSet<String>,
+        profile: &str,
+        sizes: &SizeCache,
+        mode: SizeMode,
+    ) -> Result<CleanupStats> {
+        let incremental_dir = profile_dir.join("incremental");
+        let mut stats = CleanupStats::default();
+
+        if !incremental_dir.exists() {
+            return Ok(stats);
+        }
+        let artifact_times = used_artifact_times(profile_dir, used_artifacts, outputs);
+
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        let mut files: Vec<PathBuf> = Vec::new();
+        let mut units = fs::read_dir(&incremental_dir).await?;
+        while let Some(unit) = units.next_entry().await? {
+            let unit_dir = unit.path();
+            if !unit_dir.is_dir() {
+                continue;
+            }
+
+            let mut sessions: Vec<(PathBuf, Session)> = Vec::new();
+            let mut entries = fs::read_dir(&unit_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if let Some(session) = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(parse_session)
+                {
+                    sessions.push((path, session));
+                }
+            }
+            let newest = sessions
+                .iter()
+                .filter(|(_, s)| s.entry == SessionEntry::Finalized)
+                .max_by_key(|(_, s)| s.started)
+                .map(|(_, s)| s.clone());
+
+            let in_use = newest.as_ref().is_some_and(|newest| {
+                unit_crate(&unit_dir)
+                    .and_then(|krate| artifact_times.get(krate))
+                    .is_some_and(|times| written_by_session(newest.started, times))
+            });
+            let Some(newest) = newest.filter(|_| in_use) else {
+                log::debug!("Untraced incremental unit: {}", unit_dir.display());
+                dirs.push(unit_dir);
+                continue;
+            };
+
+            // Keep the newest session and anything started after it (a build
+            // that may still be running)
+            for (path, session) in sessions {
+                if session.id == newest.id || session.started > newest.started {
+                    continue;
+                }
+                match session.entry {
+                    SessionEntry::Lock => files.push(path),
+                    SessionEntry::Finalized | SessionEntry::Working => dirs.push(path),
+                }
+            }
+        }
+
+        for path in dirs {
+            let dir_sizes = sizes.size_of(&path);
+            let size = dir_sizes.get(mode);
+            stats.add_reclaimable(profile, size, dir_sizes);
+            stats.dirs_to_remove.push(DirToRemove {
+                path,
+                size,
+                sizes: dir_sizes,
+                profile: profile.to_string(),
+                kind: RemovalKind::Incremental,
+            });
+        }
+        for path in files {
+            let file_sizes = sizes.size_of(&path);
+            let size = file_sizes.get(mode);
+            stats.add_reclaimable(profile, size, file_sizes);
+            stats.files_to_remove.push(FileToRemove {
+                path,
+                size,
+                sizes: file_sizes,
+                profile: profile.to_string(),
+                kind: RemovalKind::Incremental,
+                unit: None,
+            });
+        }
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// `s-<time>-<id>` for a session started `time`.
+    fn session_id(time: SystemTime, id: &str) -> String {
+        let mut micros = time.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
+        let mut digits = Vec::new();
+        while micros > 0 {
+            digits.push(std::char::from_digit((micros % 36) as u32, 36).unwrap());
+            micros /= 36;
+        }
+        let time: String = digits.into_iter().rev().collect();
+        format!("s-{time}-{id}")
+    }
+
+    fn make_session(unit: &Path, id: &str, suffix: &str) {
+        let dir = unit.join(format!("{id}-{suffix}"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("dep-graph.bin"), vec![0u8; 1024]).unwrap();
+        fs::write(unit.join(format!("{id}.lock")), b"").unwrap();
+    }
+
+    fn set_mtime(path: &Path, time: SystemTime) {
+        filetime::set_file_mtime(path, filetime::FileTime::from_system_time(time)).unwrap();
+    }
+
+    fn names(paths: impl Iterator<Item = PathBuf>, base: &Path) -> Vec<String> {
+        let mut names: Vec<String> = paths
+            .map(|p| p.strip_prefix(base).unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn parses_session_entries() {
+        let started = UNIX_EPOCH + Duration::from_micros(1_792_325_997_007_063);
+        let finalized = parse_session("s-hnbr724r87-1u03q78-82qkbf9k3etjpvspc2h1b3kkp").unwrap();
+        assert_eq!(finalized.id, "s-hnbr724r87-1u03q78");
+        assert_eq!(finalized.started, started);
+        assert_eq!(finalized.entry, SessionEntry::Finalized);
+        assert_eq!(
+            parse_session("s-hnbr724r87-1u03q78-working").unwrap().entry,
+            SessionEntry::Working
+        );
+        let lock = parse_session("s-hnbr724r87-1u03q78.lock").unwrap();
+        assert_eq!(
+            (lock.id.as_str(), lock.entry),
+            ("s-hnbr724r87-1u03q78", SessionEntry::Lock)
+        );
+        assert_eq!(parse_session("dep-graph.bin"), None);
+    }
+
+    #[tokio::test]
+    async fn keeps_traced_units_and_prunes_their_old_sessions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path();
+        let deps = profile_dir.join("deps");
+        let inc = profile_dir.join("incremental");
+        fs::create_dir_all(&deps).unwrap();
+
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let (old, stale, current) = (now - 3 * day, now - 2 * day, now - day);
+
+        // The lib unit of `app`: built three times, plus a crashed session
+        let lib = inc.join("app-1lib");
+        make_session(&lib, &session_id(old, "a"), "aaaa");
+        make_session(&lib, &session_id(stale, "b"), "working");
+        make_session(&lib, &session_id(current, "c"), "cccc");
+        // Its test unit, last compiled by a `cargo test` two days ago
+        let test = inc.join("app-2test");
+        make_session(&test, &session_id(stale, "d"), "dddd");
+
+        let rlib = deps.join("libapp-abc.rlib");
+        fs::write(&rlib, b"rlib").unwrap();
+        set_mtime(&rlib, current + Duration::from_secs(30));
+        let used = HashSet::from([rlib]);
+
+        let sizes = SizeCache::scan([profile_dir.to_path_buf()]).await.unwrap();
+        let stats = CleanCommand::clean_incremental_dir(
+            profile_dir,
+            &used,
+            &HashSet::new(),
+            "debug",
+            &sizes,
+            SizeMode::Apparent,
+        )
+        .await
+        .unwrap();
+
+        let dirs = names(stats.dirs_to_remove.iter().map(|d| d.path.clone()), &inc);
+        assert_eq!(
+            dirs,
+            [
+                format!("app-1lib/{}-aaaa", session_id(old, "a")),
+                format!("app-1lib/{}-working", session_id(stale, "b")),
+                "app-2test".to_string(),
+            ]
+        );
+        let files = names(stats.files_to_remove.iter().map(|f| f.path.clone()), &inc);
+        assert_eq!(
+            files,
+            [
+                format!("app-1lib/{}.lock", session_id(old, "a")),
+                format!("app-1lib/{}.lock", session_id(stale, "b")),
+            ]
+        );
+        assert!(
+            stats
+                .files_to_remove
+                .iter()
+                .all(|f| f.kind == RemovalKind::Incremental)
+        );
+        assert_eq!(stats.bytes, 3 * 1024);
+    }
+
+    #[tokio::test]
+    async fn untraced_units_are_removed_whole() {
+        let tmp = tempfile::tempdir().unwrap();
+        let inc = tmp.path().join("incremental");
+        make_session(
+            &inc.join("serde-1xyz"),
+            &session_id(SystemTime::now(), "a"),
+            "aaaa",
+        );
+
+        let sizes = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+        let stats = CleanCommand::clean_incremental_dir(
+            tmp.path(),
+            &HashSet::new(),
+            &HashSet::new(),
+            "debug",
+            &sizes,
+            SizeMode::Apparent,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(stats.dirs_to_remove.len(), 1);
+        assert_eq!(stats.dirs_to_remove[0].path, inc.join("serde-1xyz"));
+        assert!(stats.files_to_remove.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_incremental_dir_is_empty() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sizes = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+        let stats = CleanCommand::clean_incremental_dir(
+            tmp.path(),
+            &HashSet::new(),
+            &HashSet::new(),
+            "debug",
+            &sizes,
+            SizeMode::default(),
+        )
+        .await
+        .unwrap();
+        assert!(stats.dirs_to_remove.is_empty());
+    }
+}
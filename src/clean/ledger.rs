@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::stats::CleanupStats;
+
+/// Overrides the ledger location.
+const LEDGER_ENV: &str = "CARGO_CLEAN_ARTIFACT_LEDGER";
+
+/// One clean run, appended as a JSON line to the ledger.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct LedgerRecord {
+    /// Seconds since the Unix epoch
+    pub(super) timestamp: u64,
+    pub(super) workspace: PathBuf,
+    /// Traced build commands (empty for trace-free runs)
+    pub(super) commands: Vec<String>,
+    /// Nothing was removed (dry-run, or every step declined)
+    pub(super) dry_run: bool,
+    pub(super) reclaimable_files: usize,
+    pub(super) reclaimable_bytes: u64,
+    pub(super) used_bytes: u64,
+    pub(super) removed_files: usize,
+    pub(super) removed_bytes: u64,
+    pub(super) errors: usize,
+    /// Removed bytes per crate; the reclaimable bytes for a dry-run
+    pub(super) per_crate: BTreeMap<String, u64>,
+    pub(super) duration_secs: f64,
+}
+
+impl LedgerRecord {
+    pub(super) fn new(
+        workspace: PathBuf,
+        commands: Vec<String>,
+        planned: &CleanupStats,
+        removed: Option<&CleanupStats>,
+        duration: Duration,
+    ) -> Self {
+        let per_crate = removed
+            .unwrap_or(planned)
+            .per_crate
+            .iter()
+            .map(|(name, stat)| (name.clone(), stat.bytes))
+            .collect();
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            workspace,
+            commands,
+            dry_run: removed.is_none(),
+            reclaimable_files: planned.files,
+            reclaimable_bytes: planned.bytes,
+            used_bytes: planned.used_bytes,
+            removed_files: removed.map_or(0, |r| r.files),
+            removed_bytes: removed.map_or(0, |r| r.bytes),
+            errors: removed.map_or(0, |r| r.errors.len()),
+            per_crate,
+            duration_secs: duration.as_secs_f64(),
+        }
+    }
+}
+
+/// `$CARGO_CLEAN_ARTIFACT_LEDGER`, else `ledger.jsonl` in the user data dir
+/// (`$XDG_DATA_HOME` or `~/.local/share`).
+pub(super) fn default_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(LEDGER_ENV).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::home_dir().map(|h| h.join(".local/share")))?;
+    Some(data_dir.join("cargo-clean-artifact").join("ledger.jsonl"))
+}
+
+pub(super) fn append(path: &Path, record: &LedgerRecord) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let line = serde_json::to_string(record)?;
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| writeln!(f, "{line}"))
+        .with_context(|| format!("appending to {}", path.display()))
+}
+
+/// Every record in the ledger; a missing ledger is empty and unreadable
+/// lines are skipped.
+pub(super) fn load(path: &Path) -> Result<Vec<LedgerRecord>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    Ok(text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| {
+            serde_json::from_str(line)
+                .inspect_err(|e| log::warn!("{}:{}: skipping record: {e}", path.display(), i + 1))
+                .ok()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::stats::CrateStat;
+
+    #[test]
+    fn records_round_trip_and_bad_lines_are_skipped() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("nested/ledger.jsonl");
+
+        let mut planned = CleanupStats::default();
+        planned.add_reclaimable("debug", 100, Default::default());
+        planned.per_crate.insert(
+            "serde".to_string(),
+            CrateStat {
+                files: 1,
+                bytes: 100,
+                ..Default::default()
+            },
+        );
+        let record = LedgerRecord::new(
+            PathBuf::from("/ws"),
+            vec!["cargo build".to_string()],
+            &planned,
+            None,
+            Duration::from_secs(2),
+        );
+        assert!(record.dry_run);
+        assert_eq!(record.per_crate.get("serde"), Some(&100));
+
+        append(&path, &record).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| writeln!(f, "{{not json"))
+            .unwrap();
+        append(&path, &record).unwrap();
+
+        assert_eq!(load(&path).unwrap(), vec![record.clone(), record]);
+        assert!(load(&tmp.path().join("missing.jsonl")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::trace_parser::TraceResult;
+
+use super::branches::GitHead;
+
+/// Where trace manifests live, relative to the target directory.  Dot dirs
+/// are never scanned as profiles.
+const MANIFEST_DIR: &str = ".clean-artifact/traces";
+
+/// The artifacts one traced command used, stored so later runs (e.g.
+/// `watch`) can clean without re-running the build.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct TraceManifest {
+    pub(super) command: String,
+    /// Seconds since the Unix epoch when the trace finished
+    pub(super) recorded_at: u64,
+    pub(super) artifacts: Vec<PathBuf>,
+    /// Git branch checked out when the trace was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) branch: Option<String>,
+    /// Git commit checked out when the trace was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) commit: Option<String>,
+}
+
+impl TraceManifest {
+    pub(super) fn recorded_at(&self) -> SystemTime {
+        UNIX_EPOCH + std::time::Duration::from_secs(self.recorded_at)
+    }
+}
+
+/// FNV-1a, so a command maps to the same manifest file across releases.
+fn command_key(command: &str) -> String {
+    let hash = command.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Record the trace of `command`, replacing any earlier trace of it on the
+/// same git branch.
+pub(super) fn store(
+    target_dir: &Path,
+    command: &str,
+    trace: &TraceResult,
+    head: Option<&GitHead>,
+) -> Result<()> {
+    let dir = target_dir.join(MANIFEST_DIR);
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let mut artifacts: Vec<PathBuf> = trace.used_artifacts.iter().cloned().collect();
+    artifacts.sort();
+    let manifest = TraceManifest {
+        command: command.to_string(),
+        recorded_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        artifacts,
+        branch: head.and_then(|h| h.branch.clone()),
+        commit: head.map(|h| h.commit.clone()),
+    };
+    // Traces from before branches were recorded keep their file name
+    let key = match &manifest.branch {
+        Some(branch) => command_key(&format!("{command}\0{branch}")),
+        None => command_key(command),
+    };
+    let path = dir.join(format!("{key}.json"));
+    let json = serde_json::to_string_pretty(&manifest)?;
+    std::fs::write(&path, json).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Every readable manifest stored in `target_dir`.
+pub(super) fn load_all(target_dir: &Path) -> Vec<TraceManifest> {
+    let Ok(entries) = std::fs::read_dir(target_dir.join(MANIFEST_DIR)) else {
+        return Vec::new();
+    };
+    let mut manifests: Vec<TraceManifest> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .filter_map(|p| {
+            let text = std::fs::read_to_string(&p).ok()?;
+            serde_json::from_str(&text)
+                .inspect_err(|e| log::warn!("Ignoring unreadable manifest {}: {e}", p.display()))
+                .ok()
+        })
+        .collect();
+    manifests.sort_by(|a, b| a.command.cmp(&b.command));
+    manifests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_replaces_per_command_and_loads_all() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut trace = TraceResult::default();
+        trace
+            .used_artifacts
+            .insert(tmp.path().join("debug/deps/libfoo-1.rlib"));
+
+        store(tmp.path(), "cargo build", &trace, None).unwrap();
+        store(tmp.path(), "cargo build", &trace, None).unwrap();
+        store(
+            tmp.path(),
+            "cargo test --no-run",
+            &TraceResult::default(),
+            None,
+        )
+        .unwrap();
+
+        let manifests = load_all(tmp.path());
+        assert_eq!(manifests.len(), 2);
+        assert_eq!(manifests[0].command, "cargo build");
+        assert_eq!(
+            manifests[0].artifacts,
+            vec![tmp.path().join("debug/deps/libfoo-1.rlib")]
+        );
+        assert_eq!(manifests[0].branch, None);
+    }
+
+    #[test]
+    fn traces_are_kept_per_branch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let trace = TraceResult::default();
+        for branch in ["main", "feature", "main"] {
+            let head = GitHead {
+                branch: Some(branch.to_string()),
+                commit: format!("{branch}-sha"),
+            };
+            store(tmp.path(), "cargo build", &trace, Some(&head)).unwrap();
+        }
+
+        let manifests = load_all(tmp.path());
+        let mut branches: Vec<_> = manifests
+            .iter()
+            .filter_map(|m| m.branch.as_deref())
+            .collect();
+        branches.sort();
+        assert_eq!(branches, ["feature", "main"]);
+        assert!(manifests.iter().all(|m| m.commit.is_some()));
+    }
+}
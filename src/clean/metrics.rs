@@ -0,0 +1,190 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::stats::CleanupStats;
+
+/// Per-profile gauges: name, help text, value.
+type ProfileGauge = (&'static str, &'static str, fn(&Sample) -> f64);
+
+/// Values of one `(workspace, profile)` series.
+struct Sample {
+    total_dir_bytes: u64,
+    in_use_bytes: u64,
+    reclaimable_bytes: u64,
+    removed_bytes: u64,
+    removal_errors: usize,
+}
+
+const PROFILE_GAUGES: &[ProfileGauge] = &[
+    (
+        "cargo_clean_artifact_target_dir_bytes",
+        "Total size of the profile directory.",
+        |s| s.total_dir_bytes as f64,
+    ),
+    (
+        "cargo_clean_artifact_in_use_bytes",
+        "Size of deps/ artifacts kept because the build uses them.",
+        |s| s.in_use_bytes as f64,
+    ),
+    (
+        "cargo_clean_artifact_reclaimable_bytes",
+        "Size of everything planned for removal.",
+        |s| s.reclaimable_bytes as f64,
+    ),
+    (
+        "cargo_clean_artifact_removed_bytes",
+        "Size actually removed by this run (0 for a dry-run).",
+        |s| s.removed_bytes as f64,
+    ),
+    (
+        "cargo_clean_artifact_removal_errors",
+        "Files or directories that could not be removed.",
+        |s| s.removal_errors as f64,
+    ),
+];
+
+/// Escape a Prometheus label value.
+fn label_value(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render the run as Prometheus text exposition format.
+fn render_metrics(
+    workspace: &Path,
+    planned: &CleanupStats,
+    removed: Option<&CleanupStats>,
+) -> String {
+    let workspace = label_value(&workspace.display().to_string());
+    let profiles: BTreeSet<&String> = planned.per_profile.keys().collect();
+    let samples: Vec<(String, Sample)> = profiles
+        .into_iter()
+        .map(|profile| {
+            let stat = &planned.per_profile[profile];
+            let sample = Sample {
+                total_dir_bytes: stat.total_dir_bytes,
+                in_use_bytes: stat.used_bytes,
+                reclaimable_bytes: stat.bytes,
+                removed_bytes: removed
+                    .and_then(|r| r.per_profile.get(profile))
+                    .map_or(0, |p| p.bytes),
+                removal_errors: removed.map_or(0, |r| {
+                    r.errors.keys().filter(|(_, p, _)| p == profile).count()
+                }),
+            };
+            (label_value(profile), sample)
+        })
+        .collect();
+
+    let mut out = String::new();
+    for (name, help, value) in PROFILE_GAUGES {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        for (profile, sample) in &samples {
+            let _ = writeln!(
+                out,
+                "{name}{{workspace=\"{workspace}\",profile=\"{profile}\"}} {}",
+                value(sample)
+            );
+        }
+    }
+
+    if let Some(duration) = planned.trace_duration {
+        let name = "cargo_clean_artifact_trace_duration_seconds";
+        let _ = writeln!(out, "# HELP {name} Wall-clock time of the traced build.");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(
+            out,
+            "{name}{{workspace=\"{workspace}\"}} {:.3}",
+            duration.as_secs_f64()
+        );
+        let name = "cargo_clean_artifact_traced_artifacts";
+        let _ = writeln!(
+            out,
+            "# HELP {name} Distinct artifacts referenced by the traced build."
+        );
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(
+            out,
+            "{name}{{workspace=\"{workspace}\"}} {}",
+            planned.traced_artifacts
+        );
+    }
+    out
+}
+
+/// Write the metrics to `path`, atomically so a textfile collector never
+/// scrapes a half-written file.
+pub(super) fn write_metrics(
+    path: &Path,
+    workspace: &Path,
+    planned: &CleanupStats,
+    removed: Option<&CleanupStats>,
+) -> Result<()> {
+    let text = render_metrics(workspace, planned, removed);
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, text)
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .with_context(|| format!("writing metrics to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn renders_profile_and_trace_gauges() {
+        let mut planned = CleanupStats::default();
+        planned.add_reclaimable("debug", 300, Default::default());
+        let debug = planned.per_profile.get_mut("debug").unwrap();
+        debug.used_bytes = 200;
+        debug.total_dir_bytes = 1000;
+        planned.traced_artifacts = 7;
+        planned.trace_duration = Some(Duration::from_millis(1500));
+
+        let mut removed = CleanupStats::default();
+        removed.add_reclaimable("debug", 100, Default::default());
+        removed.errors.insert(
+            (
+                "foo".to_string(),
+                "debug".to_string(),
+                "libfoo-1.rlib".to_string(),
+            ),
+            anyhow::anyhow!("permission denied"),
+        );
+
+        let text = render_metrics(Path::new("/ws/a\"b"), &planned, Some(&removed));
+        let labels = r#"{workspace="/ws/a\"b",profile="debug"}"#;
+        for line in [
+            format!("cargo_clean_artifact_target_dir_bytes{labels} 1000"),
+            format!("cargo_clean_artifact_in_use_bytes{labels} 200"),
+            format!("cargo_clean_artifact_reclaimable_bytes{labels} 300"),
+            format!("cargo_clean_artifact_removed_bytes{labels} 100"),
+            format!("cargo_clean_artifact_removal_errors{labels} 1"),
+            r#"cargo_clean_artifact_trace_duration_seconds{workspace="/ws/a\"b"} 1.500"#
+                .to_string(),
+            r#"cargo_clean_artifact_traced_artifacts{workspace="/ws/a\"b"} 7"#.to_string(),
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line} in\n{text}");
+        }
+        assert!(text.contains("# TYPE cargo_clean_artifact_removed_bytes gauge"));
+    }
+
+    #[test]
+    fn dry_run_reports_nothing_removed_and_no_trace_gauges_without_trace() {
+        let mut planned = CleanupStats::default();
+        planned.add_reclaimable("release", 300, Default::default());
+
+        let text = render_metrics(Path::new("/ws"), &planned, None);
+        assert!(text.contains(
+            r#"cargo_clean_artifact_removed_bytes{workspace="/ws",profile="release"} 0"#
+        ));
+        assert!(!text.contains("trace_duration"));
+    }
+}
@@ -1,13 +1,14 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
     env,
     io::IsTerminal,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
 use anstyle::{AnsiColor, Style};
 use anyhow::{Context, Result};
-use cargo_metadata::MetadataCommand;
+use cargo_metadata::Metadata;
 use clap::ArgAction;
 use clap::{Args, ValueHint};
 use futures::future::try_join_all;
@@ -15,20 +16,57 @@ use tokio::fs;
 
 use indicatif::{ProgressBar, ProgressStyle};
 
-use crate::crate_deps::{crate_key, paint};
-use crate::trace_parser::TraceParser;
+use crate::crate_deps::{crate_key, paint, parse_interval, parse_size};
+use crate::trace_parser::{TraceParser, TraceResult};
 
+mod branches;
+mod build_dir;
+mod ci;
+mod ci_cache;
+mod cold;
+mod dep_graph;
 mod display;
+mod dupes;
+mod flapping;
+mod heuristic;
+mod history;
+mod home;
+mod hook;
+mod incremental;
+mod ledger;
+mod manifest;
+mod metrics;
 mod prompt;
+mod report;
+mod roots;
 mod scan;
 mod stats;
-
+mod toolchain;
+mod unclassified;
+mod units;
+mod watch;
+
+pub(crate) use ci_cache::CiCacheCommand;
+pub(crate) use history::StatsCommand;
+pub(crate) use home::HomeCommand;
+pub(crate) use hook::{InstallHookCommand, UninstallHookCommand};
+pub(crate) use watch::WatchCommand;
+
+use ci::TraceFailed;
+use dep_graph::DepGraph;
 use display::{
-    print_detailed_summary, print_dry_run_summary, print_profile_breakdown, print_removal_summary,
+    print_build_profiles, print_detailed_summary, print_dry_run_summary, print_estimate_notice,
+    print_profile_breakdown, print_removal_summary, print_toolchain_breakdown, print_unclassified,
 };
 use prompt::{RemovalSelection, prompt_step_by_step, select_command_interactive};
-use scan::{artifact_stem, dir_size_bytes};
-use stats::{CleanupStats, DirToRemove, FileToRemove};
+use scan::{
+    DiskSize, SizeCache, SizeMode, artifact_stem, dedupe_hard_links, discover_deps_dirs,
+    is_debug_bundle, is_split_debuginfo, output_crate_names, traced_deps_dirs,
+};
+use stats::{CleanupStats, CrateStat, DirToRemove, FileToRemove, InUseArtifact, RemovalKind};
+use toolchain::ToolchainIndex;
+use unclassified::UnclassifiedRules;
+use units::UnitIndex;
 
 /// Clean unused, old project files.
 ///
@@ -58,13 +96,39 @@ pub(crate) struct CleanCommand {
     #[clap(long, action = ArgAction::SetTrue)]
     allow_shared_target_dir: bool,
 
+    /// Target directory the build writes to, when it is not the one cargo
+    /// metadata reports for DIR. A `--target-dir` or `--manifest-path` in
+    /// the traced command is picked up without it.
+    #[clap(long, value_name = "TARGET_DIR", value_hint = ValueHint::DirPath)]
+    target_dir: Option<PathBuf>,
+
     /// Build command to trace (e.g. "cargo build --release --target wasm32-unknown-unknown").
     /// Passed to `sh -c`, so quoting and spaces work as normal.
     #[clap(short = 'c', long = "command", value_name = "COMMAND")]
     custom_command: Option<String>,
 
+    /// Also write the raw CARGO_LOG stderr of the traced command to FILE, for
+    /// replaying with --trace-log (e.g. attached to a bug report).
+    #[clap(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["trace_log", "no_trace"]
+    )]
+    save_trace_log: Option<PathBuf>,
+
+    /// Plan from a log saved with --save-trace-log instead of running a
+    /// build. Nothing is spawned and no trace manifest is stored.
+    #[clap(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["custom_command", "no_trace"]
+    )]
+    trace_log: Option<PathBuf>,
+
     /// Enable verbose output (debug logging).
-    #[clap(short = 'v', long = "verbose")]
+    #[clap(short = 'v', long = "verbose", global = true)]
     verbose: bool,
 
     /// Print the top N largest in-use artifacts found during tracing.
@@ -76,6 +140,103 @@ pub(crate) struct CleanCommand {
         default_value = "5"
     )]
     trace_stats: usize,
+
+    /// Measure sizes by logical file length instead of allocated disk blocks.
+    #[clap(long, action = ArgAction::SetTrue, conflicts_with = "disk_usage")]
+    apparent_size: bool,
+
+    /// Measure sizes by allocated disk blocks, de-duplicating hard links (default).
+    #[clap(long, action = ArgAction::SetTrue)]
+    disk_usage: bool,
+
+    /// Offer artifacts built by a rustc other than the current one as their own
+    /// category. Without -c, runs only this check (no build is traced).
+    #[clap(long, action = ArgAction::SetTrue)]
+    stale_toolchains: bool,
+
+    /// Plan without running a build: keep the newest build of every crate in
+    /// the current resolve and flag crates no longer in it. A conservative
+    /// estimate for when a build is not possible (e.g. a full disk).
+    #[clap(long, action = ArgAction::SetTrue, conflicts_with = "custom_command")]
+    no_trace: bool,
+
+    /// Non-interactive mode for pipelines: never prompt, dry-run unless -y,
+    /// and report the outcome through the exit code (see README).
+    #[clap(long, action = ArgAction::SetTrue)]
+    ci: bool,
+
+    /// With --ci, exit with the "reclaimable" code when more than SIZE could
+    /// be reclaimed (e.g. 1GiB, 500M), even if -y removed it.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, requires = "ci")]
+    fail_if_reclaimable: Option<u64>,
+
+    /// Also write a report of the plan to FILE: Markdown (.md) or a
+    /// self-contained HTML page (.html).
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    report: Option<PathBuf>,
+
+    /// Write Prometheus text-format gauges (per workspace and profile) to
+    /// FILE, e.g. for the node_exporter textfile collector.
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    metrics_file: Option<PathBuf>,
+
+    /// Do not append this run to the ledger read by `stats`.
+    #[clap(long, action = ArgAction::SetTrue)]
+    no_ledger: bool,
+
+    /// Report crates with more than N builds side by side in a profile
+    /// (usually feature sets that differ between members), and which traced
+    /// commands and crates use each build.
+    #[clap(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "2",
+        conflicts_with = "no_trace"
+    )]
+    flapping: Option<usize>,
+
+    /// Report crates locked in several versions, with the disk footprint of
+    /// each version's builds and the packages that pull it in.
+    #[clap(long, action = ArgAction::SetTrue)]
+    dupes: bool,
+
+    /// List every profile and target-triple directory with its size and age,
+    /// and offer untraced ones older than AGE (or in no stored trace) for
+    /// removal as a whole, e.g. `--cold 14d`.
+    #[clap(
+        long,
+        value_name = "AGE",
+        value_parser = parse_interval,
+        num_args = 0..=1,
+        default_missing_value = "30d",
+        conflicts_with = "no_trace"
+    )]
+    cold: Option<Duration>,
+
+    /// Also keep the N most recently built units of every crate, so switching
+    /// between feature sets or branches does not rebuild from scratch.
+    #[clap(long, value_name = "N", conflicts_with = "no_trace")]
+    keep_last: Option<usize>,
+
+    /// Also keep what the stored traces of the N most recently traced other
+    /// git branches used (branches deleted locally protect nothing).
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "no_trace"
+    )]
+    keep_branches: usize,
+
+    /// Also keep what the stored traces of BRANCH used; can be repeated.
+    #[clap(long, value_name = "BRANCH", conflicts_with = "no_trace")]
+    keep_branch: Vec<String>,
+
+    /// List what is built against CRATE, directly or not, from the dep-info
+    /// files (needs RUSTFLAGS="-Zbinary-dep-depinfo").
+    #[clap(long, value_name = "CRATE", conflicts_with = "no_trace")]
+    dependents: Option<String>,
 }
 
 impl CleanCommand {
@@ -84,18 +245,29 @@ impl CleanCommand {
         self.verbose
     }
 
+    fn size_mode(&self) -> SizeMode {
+        if self.apparent_size {
+            SizeMode::Apparent
+        } else {
+            SizeMode::DiskUsage
+        }
+    }
+
     /// Clean up `target` of cargo.
     ///
     /// We only remove build outputs for outdated dependencies.
     pub(super) async fn remove_unused_files_of_cargo(
         &self,
         git_dir: &Path,
-        cmd: &str,
+        cmd: Option<&str>,
     ) -> Result<CleanupStats> {
-        let metadata = MetadataCommand::new().current_dir(git_dir).exec();
-
-        let metadata = match metadata {
-            Ok(metadata) => metadata,
+        // A `--manifest-path` in the traced command selects the workspace
+        let manifest_path = match cmd {
+            Some(cmd) => roots::cargo_path_option(cmd, "--manifest-path", git_dir)?,
+            None => None,
+        };
+        let (metadata, build_dir) = match build_dir::metadata(git_dir, manifest_path.as_deref()) {
+            Ok(found) => found,
             Err(e) => {
                 eprintln!("⚠️  Warning: Not a cargo project or failed to read metadata");
                 eprintln!("   Directory: {}", git_dir.display());
@@ -104,55 +276,299 @@ impl CleanCommand {
             }
         };
 
-        let target_dir = metadata.target_directory.as_std_path().to_path_buf();
+        // `--target-dir` wins over the one the traced command passes to cargo,
+        // which wins over the one of the metadata
+        let metadata_target = metadata.target_directory.as_std_path();
+        let target_dir = match (&self.target_dir, cmd) {
+            (Some(dir), _) => std::path::absolute(dir)?,
+            (None, Some(cmd)) => roots::cargo_path_option(cmd, "--target-dir", git_dir)?
+                .unwrap_or_else(|| metadata_target.to_path_buf()),
+            (None, None) => metadata_target.to_path_buf(),
+        };
+        // Without a separate build-dir, intermediates follow the target dir
+        let build_dir = if build_dir == metadata_target {
+            target_dir.clone()
+        } else {
+            build_dir
+        };
+        if target_dir != metadata_target
+            && !target_dir.starts_with(metadata.workspace_root.as_std_path())
+        {
+            self.confirm_shared_target(
+                &format!("the target directory is {}", target_dir.display()),
+                "target directory outside the workspace",
+            )?;
+        }
         log::debug!("Target directory: {}", target_dir.display());
+        if build_dir != target_dir {
+            log::debug!("Build directory: {}", build_dir.display());
+        }
 
-        if !target_dir.exists() {
-            eprintln!(
-                "⚠️  Warning: Target directory does not exist: {}",
-                target_dir.display()
+        let (stats, build_dir) = match cmd {
+            // The traced build may write elsewhere; checked once its roots are known
+            cmd if self.trace_log.is_some() || (cmd.is_some() && !self.no_trace) => {
+                self.remove_unused_files_with_trace(
+                    git_dir,
+                    &metadata,
+                    &target_dir,
+                    &build_dir,
+                    cmd,
+                )
+                .await?
+            }
+            _ if missing_target_dir(&target_dir) => return Ok(CleanupStats::default()),
+            _ if self.no_trace => (
+                self.remove_unused_files_without_trace(&metadata, &build_dir)
+                    .await?,
+                build_dir,
+            ),
+            _ => (
+                self.remove_other_toolchain_files(&build_dir).await?,
+                build_dir,
+            ),
+        };
+
+        if self.dupes {
+            // After the build, so the footprint includes what it just produced
+            let duplicates =
+                dupes::find_duplicates(&metadata, &build_dir, self.size_mode()).await?;
+            dupes::print_duplicates(&duplicates);
+        }
+        Ok(stats)
+    }
+
+    /// Offer every `deps/` file built by another rustc, without tracing a build.
+    async fn remove_other_toolchain_files(&self, target_dir: &Path) -> Result<CleanupStats> {
+        let deps_dirs = discover_deps_dirs(target_dir);
+        print_build_profiles(&deps_dirs);
+
+        let sizes = SizeCache::scan(
+            deps_dirs
+                .iter()
+                .filter_map(|(d, _)| d.parent())
+                .map(Path::to_path_buf),
+        )
+        .await?;
+        let index = Self::toolchain_index(target_dir, &deps_dirs).await?;
+
+        let mut stats = CleanupStats::default();
+        for (deps_dir, profile) in &deps_dirs {
+            let mut profile_stats = CleanupStats::default();
+            let mut to_remove = Vec::new();
+            let mut entries = fs::read_dir(deps_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let Some(stem) = artifact_stem(&path) else {
+                    continue;
+                };
+                if index.is_other_toolchain(&stem) {
+                    let file_sizes = sizes.size_of(&path);
+                    to_remove.push((path, file_sizes));
+                } else {
+                    let sz = sizes.size_of(&path).get(self.size_mode());
+                    profile_stats.used_bytes += sz;
+                    profile_stats
+                        .per_profile
+                        .entry(profile.clone())
+                        .or_default()
+                        .used_bytes += sz;
+                }
+            }
+            Self::tally_files(
+                &mut profile_stats,
+                to_remove,
+                profile,
+                RemovalKind::OtherToolchain,
+                &sizes,
+                self.size_mode(),
             );
-            eprintln!("   Run your build command first to generate build artifacts.");
-            return Ok(CleanupStats::default());
+            profile_stats
+                .per_profile
+                .entry(profile.clone())
+                .or_default()
+                .total_dir_bytes = deps_dir
+                .parent()
+                .map(|p| sizes.size_of(p).get(self.size_mode()))
+                .unwrap_or(0);
+            stats.merge_from(profile_stats);
         }
 
-        self.remove_unused_files_with_trace(git_dir, &target_dir, cmd)
+        self.record_toolchains(&mut stats, &index, &deps_dirs, &sizes);
+        Ok(stats)
+    }
+
+    /// Move planned files built by an older rustc into their own category.
+    async fn split_other_toolchains(
+        &self,
+        stats: &mut CleanupStats,
+        target_dir: &Path,
+        deps_dirs: &[(PathBuf, String)],
+        sizes: &SizeCache,
+    ) -> Result<()> {
+        let index = Self::toolchain_index(target_dir, deps_dirs).await?;
+        for file in &mut stats.files_to_remove {
+            if artifact_stem(&file.path).is_some_and(|s| index.is_other_toolchain(&s)) {
+                file.kind = RemovalKind::OtherToolchain;
+            }
+        }
+        self.record_toolchains(stats, &index, deps_dirs, sizes);
+        Ok(())
+    }
+
+    async fn toolchain_index(
+        target_dir: &Path,
+        deps_dirs: &[(PathBuf, String)],
+    ) -> Result<ToolchainIndex> {
+        let target_dir = target_dir.to_path_buf();
+        let deps_dirs: Vec<PathBuf> = deps_dirs.iter().map(|(d, _)| d.clone()).collect();
+        tokio::task::spawn_blocking(move || ToolchainIndex::build(&target_dir, &deps_dirs))
             .await
+            .context("toolchain scan task panicked")
+    }
+
+    /// Fill the per-toolchain breakdown of every file in `deps_dirs`.
+    fn record_toolchains(
+        &self,
+        stats: &mut CleanupStats,
+        index: &ToolchainIndex,
+        deps_dirs: &[(PathBuf, String)],
+        sizes: &SizeCache,
+    ) {
+        let mut per_toolchain: HashMap<String, CrateStat> = HashMap::new();
+        for (deps_dir, _) in deps_dirs {
+            let Ok(entries) = std::fs::read_dir(deps_dir) else {
+                continue;
+            };
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                let Some(stem) = artifact_stem(&path) else {
+                    continue;
+                };
+                let toolchain = index.version_of(&stem).unwrap_or("unknown");
+                let entry = per_toolchain.entry(toolchain.to_string()).or_default();
+                entry.files += 1;
+                entry.bytes += sizes.size_of(&path).get(self.size_mode());
+            }
+        }
+        stats.per_toolchain = per_toolchain;
+        stats.current_toolchain = index.current.clone();
     }
 
-    /// Remove unused files using cargo trace mode
+    /// When the traced build wrote somewhere else than `target_dir` and
+    /// `build_dir` (say a `--target-dir` hidden in a script), switch to the
+    /// target root its artifacts were found in.
+    fn adopt_traced_roots(
+        &self,
+        trace: &mut TraceResult,
+        target_dir: &Path,
+        build_dir: &Path,
+        workspace_root: &Path,
+    ) -> Result<(PathBuf, PathBuf)> {
+        let roots = roots::traced_roots(&trace.unrooted);
+        let Some(root) = roots.first().filter(|_| roots.len() == 1) else {
+            if !roots.is_empty() {
+                println!(
+                    "🎯 The traced build used {} target directories; pass --target-dir to pick one",
+                    roots.len()
+                );
+                println!();
+            }
+            return Ok((target_dir.to_path_buf(), build_dir.to_path_buf()));
+        };
+        if !trace.used_artifacts.is_empty() {
+            println!(
+                "🎯 The traced build also used {}; pass --target-dir to clean it",
+                root.display()
+            );
+            println!();
+            return Ok((target_dir.to_path_buf(), build_dir.to_path_buf()));
+        }
+        println!(
+            "🎯 The traced build wrote to {}, not {}",
+            root.display(),
+            target_dir.display()
+        );
+        println!();
+        if !root.starts_with(workspace_root) {
+            self.confirm_shared_target(
+                &format!("the traced build wrote to {}", root.display()),
+                "traced target directory outside the workspace",
+            )?;
+        }
+        let (adopted, rest) = std::mem::take(&mut trace.unrooted)
+            .into_iter()
+            .partition(|a: &PathBuf| a.starts_with(root));
+        trace.used_artifacts.extend::<HashSet<PathBuf>>(adopted);
+        trace.unrooted = rest;
+        Ok((root.clone(), root.clone()))
+    }
+
+    /// Remove unused files using cargo trace mode, and tell which build dir
+    /// was cleaned: the traced build may have written to another one.
     async fn remove_unused_files_with_trace(
         &self,
         project_dir: &Path,
+        metadata: &Metadata,
         target_dir: &Path,
-        cmd: &str,
-    ) -> Result<CleanupStats> {
-        let parser = TraceParser::new(target_dir.to_path_buf());
-        let trace_result = parser
-            .trace(project_dir, cmd)
-            .await
-            .context("Failed to trace build command")?;
+        build_dir: &Path,
+        cmd: Option<&str>,
+    ) -> Result<(CleanupStats, PathBuf)> {
+        let workspace_root = metadata.workspace_root.as_std_path();
+        let parser = TraceParser::new(target_dir.to_path_buf())
+            .with_build_dir(build_dir.to_path_buf())
+            .save_log_to(self.save_trace_log.clone());
+        let head = branches::head(workspace_root);
+        let mut trace_result = match &self.trace_log {
+            // A replay describes an earlier build, not the current tree
+            Some(log) => parser.parse_log(log)?,
+            None => {
+                let cmd = cmd.context("no build command to trace")?;
+                let trace_result = parser.trace(project_dir, cmd).await.context(TraceFailed)?;
+                if let Some(path) = &self.save_trace_log {
+                    println!("📼 Trace log saved to {}", path.display());
+                    println!();
+                }
+                trace_result
+            }
+        };
+        let (target_dir, build_dir) =
+            self.adopt_traced_roots(&mut trace_result, target_dir, build_dir, workspace_root)?;
+        let (target_dir, build_dir) = (target_dir.as_path(), build_dir.as_path());
+        if missing_target_dir(target_dir) {
+            return Ok((CleanupStats::default(), build_dir.to_path_buf()));
+        }
+        if let Some(cmd) = cmd
+            && self.trace_log.is_none()
+            && let Err(e) = manifest::store(target_dir, cmd, &trace_result, head.as_ref())
+        {
+            log::warn!("Failed to store trace manifest: {e:#}");
+        }
+
+        // Artifacts of other branches' stored traces are kept like traced ones
+        let mut used = trace_result.used_artifacts.clone();
+        if self.keep_branches > 0 || !self.keep_branch.is_empty() {
+            match branches::local_branches(workspace_root) {
+                Some(existing) => {
+                    let manifests = manifest::load_all(target_dir);
+                    let current = head.as_ref().and_then(|h| h.branch.as_deref());
+                    branches::protect(
+                        &branches::protecting(
+                            &manifests,
+                            current,
+                            self.keep_branches,
+                            &self.keep_branch,
+                            &existing,
+                        ),
+                        &mut used,
+                    );
+                }
+                None => log::warn!("Not a git repository, not protecting other branches"),
+            }
+        }
 
         // Derive all deps/ directories to scan from the trace result paths.
         // This automatically handles cross-compilation targets like
         // target/wasm32-unknown-unknown/wasm-dev/deps/.
-        let mut scan_dirs: Vec<(PathBuf, String)> = Vec::new(); // (path, display_profile)
-        for artifact in &trace_result.used_artifacts {
-            if let Some(parent) = artifact.parent()
-                && parent.file_name().is_some_and(|n| n == "deps")
-                && parent.starts_with(target_dir)
-                && !scan_dirs.iter().any(|(d, _)| d == parent)
-            {
-                // Display name: strip target_dir prefix and trailing "/deps"
-                let display = parent
-                    .strip_prefix(target_dir)
-                    .ok()
-                    .and_then(|p| p.parent()) // drop "deps" component
-                    .map(|p| p.to_string_lossy().into_owned())
-                    .unwrap_or_else(|| "unknown".to_string());
-                scan_dirs.push((parent.to_path_buf(), display));
-            }
-        }
+        let scan_dirs = traced_deps_dirs(&trace_result.used_artifacts, build_dir);
 
         log::debug!("Scanning {} deps directories", scan_dirs.len());
         for (dir, name) in &scan_dirs {
@@ -160,53 +576,70 @@ impl CleanCommand {
         }
 
         // Always show which profiles were observed in the trace
-        if scan_dirs.is_empty() {
-            println!("📂 Build profiles: \x1b[2m(none detected)\x1b[0m");
-        } else {
-            let profile_list: Vec<&str> = scan_dirs.iter().map(|(_, p)| p.as_str()).collect();
-            println!(
-                "📂 Build profiles: \x1b[1;36m{}\x1b[0m",
-                profile_list.join("\x1b[0m, \x1b[1;36m")
-            );
-        }
-        println!();
+        print_build_profiles(&scan_dirs);
+
+        // Walk every observed profile dir once (in parallel) and reuse the sizes
+        // for both the in-use listing and the removal plan.
+        let sizes = SizeCache::scan(
+            scan_dirs
+                .iter()
+                .filter_map(|(d, _)| d.parent())
+                .filter(|p| p.exists())
+                .map(Path::to_path_buf),
+        )
+        .await?;
+
+        let graph = DepGraph::load(scan_dirs.iter().map(|(d, _)| d.as_path()), workspace_root);
+
+        let mut in_use: Vec<InUseArtifact> = trace_result
+            .used_artifacts
+            .iter()
+            .filter_map(|path| {
+                let size = sizes.get(path)?.get(self.size_mode());
+                // The profile whose directory holds the artifact
+                let profile = scan_dirs
+                    .iter()
+                    .find(|(d, _)| d.parent().is_some_and(|p| path.starts_with(p)))
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_default();
+                // Consumers recorded in dep-info beat the span's log target
+                let consumers = graph.used_by(path);
+                let mut used_by: Vec<String> = if consumers.is_empty() {
+                    trace_result
+                        .used_by
+                        .get(path)
+                        .map(|s| s.iter().cloned().collect())
+                        .unwrap_or_default()
+                } else {
+                    consumers.into_iter().collect()
+                };
+                used_by.sort();
+                Some(InUseArtifact {
+                    path: path.clone(),
+                    profile,
+                    size,
+                    used_by,
+                })
+            })
+            .collect();
+        in_use.sort_by_key(|a| std::cmp::Reverse(a.size));
 
         // in-use artifact breakdown (always shown unless --trace-stats 0)
         if self.trace_stats > 0 {
             let n = self.trace_stats;
-            let mut sized: Vec<(PathBuf, u64)> = trace_result
-                .used_artifacts
-                .iter()
-                .filter_map(|p| std::fs::metadata(p).ok().map(|m| (p.clone(), m.len())))
-                .collect();
-            sized.sort_by(|a, b| b.1.cmp(&a.1));
-            let total = sized.len();
+            let total = in_use.len();
             let shown = n.min(total);
             println!(
                 "\x1b[1;33m📦 Top {} in-use artifacts\x1b[0m \x1b[2m({} total):\x1b[0m",
                 shown, total
             );
-            for (i, (path, size)) in sized.iter().take(n).enumerate() {
-                // Derive profile and filename separately
-                let rel = path.strip_prefix(target_dir).ok();
-                let profile = rel
-                    .and_then(|r| r.parent()) // strip filename
-                    .and_then(|r| r.parent()) // strip "deps"
-                    .map(|r| r.to_string_lossy().into_owned())
-                    .unwrap_or_default();
-                let filename = path
+            for (i, artifact) in in_use.iter().take(n).enumerate() {
+                let filename = artifact
+                    .path
                     .file_name()
                     .and_then(|n| n.to_str())
                     .unwrap_or("unknown");
-                let users: Vec<&str> = trace_result
-                    .used_by
-                    .get(path)
-                    .map(|s| {
-                        let mut v: Vec<&str> = s.iter().map(|x| x.as_str()).collect();
-                        v.sort();
-                        v
-                    })
-                    .unwrap_or_default();
+                let users: Vec<&str> = artifact.used_by.iter().map(String::as_str).collect();
                 let used_by = if users.is_empty() {
                     None
                 } else {
@@ -216,9 +649,9 @@ impl CleanCommand {
                     "{}",
                     crate::theme::format_artifact_line(
                         i + 1,
-                        &profile,
+                        &artifact.profile,
                         filename,
-                        *size,
+                        artifact.size,
                         used_by,
                         &crate::theme::IN_USE
                     )
@@ -233,11 +666,49 @@ impl CleanCommand {
             println!();
         }
 
-        let mut stats = CleanupStats::default();
+        dep_graph::print_missing_sources(&graph);
+        if let Some(name) = &self.dependents {
+            dep_graph::print_dependents(&graph, name);
+        }
+
+        if let Some(threshold) = self.flapping {
+            // Stored manifests include the one just recorded for `cmd`
+            let manifests = manifest::load_all(target_dir);
+            let crates: Vec<_> = scan_dirs
+                .iter()
+                .flat_map(|(deps_dir, profile)| {
+                    flapping::find_flapping(
+                        deps_dir,
+                        profile,
+                        &trace_result.used_by,
+                        &manifests,
+                        &sizes,
+                        self.size_mode(),
+                        threshold,
+                    )
+                })
+                .collect();
+            flapping::print_flapping(
+                &crates,
+                threshold,
+                flapping::uses_resolver_v1(&workspace_root.join("Cargo.toml")),
+            );
+        }
+
+        let mut stats = CleanupStats {
+            in_use,
+            traced_artifacts: trace_result.used_artifacts.len(),
+            trace_duration: Some(trace_result.duration),
+            ..Default::default()
+        };
         let mut found_any_profile = false;
 
         for (deps_dir, display_profile) in &scan_dirs {
-            if !deps_dir.exists() {
+            let Some(profile_dir) = deps_dir.parent() else {
+                continue;
+            };
+            let unit_layout = build_dir::has_unit_layout(profile_dir);
+            if !deps_dir.exists() && !unit_layout {
                 log::debug!("Profile directory does not exist: {}", deps_dir.display());
                 continue;
             }
@@ -245,12 +716,35 @@ impl CleanCommand {
             found_any_profile = true;
 
             // Total size of the entire profile dir (deps + incremental + build + …)
-            let total_dir_bytes = deps_dir.parent().map(dir_size_bytes).unwrap_or(0);
-
-            let mut profile_stats = self
-                .clean_with_trace_result(deps_dir, &trace_result.used_artifacts, display_profile)
+            let total_dir_bytes = sizes.size_of(profile_dir).get(self.size_mode());
+
+            // With a separate build-dir the final outputs stay in the target dir
+            let mut outputs = output_crate_names(profile_dir);
+            outputs.extend(output_crate_names(&target_dir.join(display_profile)));
+
+            let mut profile_stats = if unit_layout {
+                build_dir::clean_unit_dirs(
+                    profile_dir,
+                    &used,
+                    &outputs,
+                    display_profile,
+                    &sizes,
+                    self.size_mode(),
+                    self.keep_last.unwrap_or(0),
+                )
+            } else {
+                Self::clean_with_trace_result(
+                    deps_dir,
+                    &used,
+                    &outputs,
+                    display_profile,
+                    &sizes,
+                    self.size_mode(),
+                    self.keep_last.unwrap_or(0),
+                )
                 .await
-                .context(format!("Failed to clean profile: {display_profile}"))?;
+                .context(format!("Failed to clean profile: {display_profile}"))?
+            };
 
             // Attach total dir size to this profile's stat entry
             profile_stats
@@ -260,16 +754,73 @@ impl CleanCommand {
                 .total_dir_bytes = total_dir_bytes;
 
             // Clean stale incremental sessions for this profile
-            if let Some(profile_dir) = deps_dir.parent() {
-                match CleanCommand::clean_incremental_dir(profile_dir, display_profile).await {
-                    Ok(inc_stats) => profile_stats.merge_from(inc_stats),
-                    Err(e) => log::warn!("Failed to scan incremental dir: {e}"),
-                }
+            match CleanCommand::clean_incremental_dir(
+                profile_dir,
+                &used,
+                &outputs,
+                display_profile,
+                &sizes,
+                self.size_mode(),
+            )
+            .await
+            {
+                Ok(inc_stats) => profile_stats.merge_from(inc_stats),
+                Err(e) => log::warn!("Failed to scan incremental dir: {e}"),
             }
 
             stats.merge_from(profile_stats);
         }
 
+        if self.stale_toolchains {
+            self.split_other_toolchains(&mut stats, build_dir, &scan_dirs, &sizes)
+                .await?;
+        }
+
+        if let Some(max_age) = self.cold {
+            let traced: HashSet<PathBuf> = scan_dirs
+                .iter()
+                .filter_map(|(d, _)| d.parent())
+                .map(Path::to_path_buf)
+                .collect();
+            // With a separate build-dir, the target dir keeps profiles of its own
+            let mut roots = vec![build_dir];
+            if build_dir != target_dir {
+                roots.push(target_dir);
+            }
+            // Size whole top-level dirs so a cold target triple can be offered
+            let tops: BTreeSet<PathBuf> = roots
+                .iter()
+                .flat_map(|root| {
+                    cold::discover_profile_dirs(root)
+                        .into_iter()
+                        .filter_map(|(_, name)| name.split('/').next().map(|top| root.join(top)))
+                })
+                .collect();
+            let cold_sizes = SizeCache::scan(tops).await?;
+            let now = SystemTime::now();
+            let profiles = cold::profile_dirs(
+                &roots,
+                &traced,
+                &manifest::load_all(target_dir),
+                &cold_sizes,
+                self.size_mode(),
+                max_age,
+                now,
+            );
+            cold::print_profile_dirs(&profiles, now);
+            stats.add_cold_dirs(cold::cold_dirs(&profiles), &cold_sizes, self.size_mode());
+        }
+
+        // Name removals after their package, version, features and profile
+        let packages = units::package_dirs(metadata);
+        let mut unit_index = UnitIndex::default();
+        for (deps_dir, profile) in &scan_dirs {
+            if let Some(profile_dir) = deps_dir.parent() {
+                unit_index.extend(UnitIndex::load(profile_dir, profile, &graph, &packages));
+            }
+        }
+        unit_index.annotate(&mut stats);
+
         if !found_any_profile {
             eprintln!("⚠️  Warning: No traced artifact directories found.");
             eprintln!(
@@ -278,20 +829,25 @@ impl CleanCommand {
             eprintln!("   Target directory: {}", target_dir.display());
         }
 
-        Ok(stats)
+        Ok((stats, build_dir.to_path_buf()))
     }
 
     /// Clean artifacts in a deps directory based on trace results.
     /// Uses stem-based grouping: all files sharing a `crate-HASH` stem with a
-    /// used `.rlib`/`.rmeta` are kept. This catches `.dwo`, `.o`, `.d`, etc.
+    /// used `.rlib`/`.rmeta` are kept. This catches `.dwo`, `.o`, `.d`, etc.,
+    /// and directory-shaped debuginfo bundles like `foo-HASH.dSYM/`.
     ///
-    /// Additionally protects files whose crate name matches a current build output
-    /// in the parent profile directory (the final binary / library / wasm).
+    /// Additionally protects files whose crate name is in `outputs`, the final
+    /// binaries / libraries / wasm of the profile (see `output_crate_names`),
+    /// and retains the `keep_last` most recent builds of every crate.
     async fn clean_with_trace_result(
-        &self,
         deps_dir: &Path,
         used_artifacts: &std::collections::HashSet<PathBuf>,
+        outputs: &HashSet<String>,
         profile: &str,
+        sizes: &SizeCache,
+        mode: SizeMode,
+        keep_last: usize,
     ) -> Result<CleanupStats> {
         // Build the set of used stems from artifacts that live in this deps dir
         let mut used_stems: std::collections::HashSet<String> = std::collections::HashSet::new();
@@ -303,50 +859,47 @@ impl CleanCommand {
             }
         }
 
-        // Build a set of current output crate names from files directly in the
-        // parent profile directory (e.g. target/release/).  Files there are the
-        // final build outputs — keep their corresponding deps/ intermediates.
-        let mut protected_crate_names: std::collections::HashSet<String> =
-            std::collections::HashSet::new();
-        if let Some(profile_dir) = deps_dir.parent() {
-            if let Ok(mut profile_entries) = fs::read_dir(profile_dir).await {
-                while let Some(pe) = profile_entries.next_entry().await.ok().flatten() {
-                    let p = pe.path();
-                    if p.is_file()
-                        && let Some(name) = p.file_stem().and_then(|s| s.to_str())
-                    {
-                        let name = name.strip_prefix("lib").unwrap_or(name);
-                        // Normalize hyphens (final binary names use them; artifacts use _)
-                        let normalized = name.replace('-', "_");
-                        protected_crate_names.insert(normalized);
-                    }
-                }
-            }
-            log::debug!(
-                "Protected crate names from {}: {:?}",
-                profile_dir.display(),
-                protected_crate_names
-            );
-        }
+        // The final build outputs (e.g. target/release/) keep their deps/
+        // intermediates
+        let protected_crate_names = outputs;
+        log::debug!(
+            "Protected crate names for {}: {:?}",
+            deps_dir.display(),
+            protected_crate_names
+        );
+
+        let recent_stems = if keep_last > 0 {
+            scan::recent_stems(deps_dir, keep_last)
+        } else {
+            HashSet::new()
+        };
+        let mut retained_stems = HashSet::new();
 
+        let rules = UnclassifiedRules::new(used_artifacts, protected_crate_names, sizes);
         let mut stats = CleanupStats::default();
+        let mut to_remove = Vec::new();
+        let mut bundles = Vec::new();
         let mut entries = fs::read_dir(deps_dir).await?;
 
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
-            if path.is_dir() {
+            let is_bundle = path.is_dir();
+            if is_bundle && !is_debug_bundle(&path) {
                 continue;
             }
 
-            // Derive stem; skip files with no recognisable artifact stem
-            let stem = match artifact_stem(&path) {
-                Some(s) => s,
-                None => continue,
+            // Files with no recognisable artifact stem go to the rules
+            let Some(stem) = artifact_stem(&path) else {
+                if !is_bundle {
+                    let size = sizes.size_of(&path).get(mode);
+                    stats.record_unclassified(&rules, path, profile, size);
+                }
+                continue;
             };
 
             // Keep any file sharing a stem with a traced artifact
             if used_stems.contains(&stem) {
-                let sz = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+                let sz = sizes.size_of(&path).get(mode);
                 stats.used_bytes += sz;
                 stats
                     .per_profile
@@ -359,7 +912,7 @@ impl CleanCommand {
             // Keep any file whose crate name matches a current build output
             // (the root artifact is not in the trace since nothing depends on it)
             if protected_crate_names.contains(&crate_key(&path)) {
-                let sz = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+                let sz = sizes.size_of(&path).get(mode);
                 stats.used_bytes += sz;
                 stats
                     .per_profile
@@ -369,126 +922,110 @@ impl CleanCommand {
                 continue;
             }
 
-            // Unused – mark for removal
-            let size = fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
-            let ck = crate_key(&path);
+            // Keep the most recent builds of the crate (`--keep-last`)
+            if recent_stems.contains(&stem) {
+                stats.add_retained(profile, sizes.size_of(&path).get(mode));
+                retained_stems.insert(stem);
+                continue;
+            }
 
-            stats.files_to_remove.push(FileToRemove {
-                path: path.clone(),
-                size,
-                profile: profile.to_string(),
-            });
-            stats.files += 1;
-            stats.bytes += size;
-            stats.per_crate.entry(ck).or_default().files += 1;
-            stats.per_crate.entry(crate_key(&path)).or_default().bytes += size;
-            stats
-                .per_profile
-                .entry(profile.to_string())
-                .or_default()
-                .files += 1;
-            stats
-                .per_profile
-                .entry(profile.to_string())
-                .or_default()
-                .bytes += size;
+            // Unused – mark for removal (tallied below, once hard links are resolved)
+            if is_bundle {
+                bundles.push(path);
+            } else {
+                to_remove.push((path.clone(), sizes.size_of(&path)));
+            }
         }
 
+        Self::tally_files(
+            &mut stats,
+            to_remove,
+            profile,
+            RemovalKind::Artifact,
+            sizes,
+            mode,
+        );
+        Self::tally_debug_bundles(&mut stats, bundles, profile, sizes, mode);
+        stats.retained_builds = retained_stems.len();
+
         Ok(stats)
     }
 
-    /// Scan `<profile_dir>/incremental/` and mark stale sessions for removal.
-    ///
-    /// Cargo stores incremental data in directories named `<crate_name>-<session_hash>/`.
-    /// Multiple stale sessions accumulate over time. We keep only the most-recently
-    /// modified session per crate name and mark the rest for removal.
-    async fn clean_incremental_dir(profile_dir: &Path, profile: &str) -> Result<CleanupStats> {
-        let incremental_dir = profile_dir.join("incremental");
-        let mut stats = CleanupStats::default();
-
-        if !incremental_dir.exists() {
-            return Ok(stats);
-        }
-
-        // Collect all subdirectory entries with their mtime
-        let mut entries: Vec<(PathBuf, std::time::SystemTime)> = Vec::new();
-        let mut dir_iter = fs::read_dir(&incremental_dir).await?;
-        while let Some(entry) = dir_iter.next_entry().await? {
-            let path = entry.path();
-            if path.is_dir() {
-                let mtime = fs::metadata(&path)
-                    .await
-                    .and_then(|m| m.modified())
-                    .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
-                entries.push((path, mtime));
-            }
-        }
-
-        // Group by crate name (split on last '-' to separate session hash)
-        let mut by_crate: HashMap<String, Vec<(PathBuf, std::time::SystemTime)>> = HashMap::new();
-        for (path, mtime) in entries {
-            let dir_name = path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("")
-                .to_string();
-            // Split on last hyphen: <crate_name>-<session_hash>
-            let crate_name = match dir_name.rfind('-') {
-                Some(pos) => dir_name[..pos].to_string(),
-                None => dir_name,
-            };
-            by_crate.entry(crate_name).or_default().push((path, mtime));
+    /// Record unused debuginfo bundle directories of `profile`, removed in the
+    /// same step as the files of their unit.
+    fn tally_debug_bundles(
+        stats: &mut CleanupStats,
+        bundles: Vec<PathBuf>,
+        profile: &str,
+        sizes: &SizeCache,
+        mode: SizeMode,
+    ) {
+        for path in bundles {
+            let dir_sizes = sizes.size_of(&path);
+            let size = dir_sizes.get(mode);
+            stats.add_reclaimable(profile, size, dir_sizes);
+            stats.add_debuginfo(profile, size);
+            let crate_entry = stats.per_crate.entry(crate_key(&path)).or_default();
+            crate_entry.files += 1;
+            crate_entry.bytes += size;
+            stats.dirs_to_remove.push(DirToRemove {
+                path,
+                size,
+                sizes: dir_sizes,
+                profile: profile.to_string(),
+                kind: RemovalKind::Artifact,
+            });
         }
+    }
 
-        // For each crate, keep the newest session, mark the rest for removal
-        for (_crate_name, mut sessions) in by_crate {
-            if sessions.len() <= 1 {
-                continue;
-            }
-            // Sort newest first
-            sessions.sort_by(|a, b| b.1.cmp(&a.1));
-            // Keep index 0 (newest), remove the rest
-            for (path, _) in sessions.into_iter().skip(1) {
-                let size = dir_size_bytes(&path);
-                stats.dirs_to_remove.push(DirToRemove {
-                    path,
-                    size,
-                    profile: profile.to_string(),
-                });
-                stats.files += 1;
-                stats.bytes += size;
-                stats
-                    .per_profile
-                    .entry(profile.to_string())
-                    .or_default()
-                    .files += 1;
-                stats
-                    .per_profile
-                    .entry(profile.to_string())
-                    .or_default()
-                    .bytes += size;
+    /// Record `to_remove` as reclaimable files of `kind`, after resolving hard links.
+    fn tally_files(
+        stats: &mut CleanupStats,
+        mut to_remove: Vec<(PathBuf, DiskSize)>,
+        profile: &str,
+        kind: RemovalKind,
+        sizes: &SizeCache,
+        mode: SizeMode,
+    ) {
+        dedupe_hard_links(to_remove.iter_mut().map(|(p, s)| (p.as_path(), s)), sizes);
+
+        for (path, file_sizes) in to_remove {
+            let size = file_sizes.get(mode);
+            let ck = crate_key(&path);
+            stats.add_reclaimable(profile, size, file_sizes);
+            if is_split_debuginfo(&path) {
+                stats.add_debuginfo(profile, size);
             }
+            let crate_entry = stats.per_crate.entry(ck).or_default();
+            crate_entry.files += 1;
+            crate_entry.bytes += size;
+            stats.files_to_remove.push(FileToRemove {
+                path,
+                size,
+                sizes: file_sizes,
+                profile: profile.to_string(),
+                kind,
+                unit: None,
+            });
         }
-
-        Ok(stats)
     }
 
     async fn actually_remove_files(
-        &self,
         stats: &CleanupStats,
         sel: &RemovalSelection,
     ) -> Result<CleanupStats> {
         let mut removal_stats = CleanupStats::default();
 
-        let total = if sel.remove_files {
-            stats.files_to_remove.len()
-        } else {
-            0
-        } + if sel.remove_dirs {
-            stats.dirs_to_remove.len()
-        } else {
-            0
-        };
+        let total = stats
+            .files_to_remove
+            .iter()
+            .filter(|f| sel.contains(f.kind))
+            .count()
+            + stats
+                .dirs_to_remove
+                .iter()
+                .filter(|d| sel.contains(d.kind))
+                .count();
         let pb = ProgressBar::new(total as u64);
         pb.set_style(
             ProgressStyle::default_bar()
@@ -498,21 +1035,23 @@ impl CleanCommand {
         );
         pb.set_message("Removing...");
 
-        for file_info in stats.files_to_remove.iter().filter(|_| sel.remove_files) {
+        for file_info in stats
+            .files_to_remove
+            .iter()
+            .filter(|f| sel.contains(f.kind))
+        {
             let ck = crate_key(&file_info.path);
             match fs::remove_file(&file_info.path).await {
                 Ok(_) => {
-                    removal_stats.files += 1;
-                    removal_stats.bytes += file_info.size;
+                    removal_stats.add_reclaimable(
+                        &file_info.profile,
+                        file_info.size,
+                        file_info.sizes,
+                    );
                     let entry = removal_stats.per_crate.entry(ck.clone()).or_default();
                     entry.files += 1;
                     entry.bytes += file_info.size;
-                    let profile_entry = removal_stats
-                        .per_profile
-                        .entry(file_info.profile.clone())
-                        .or_default();
-                    profile_entry.files += 1;
-                    profile_entry.bytes += file_info.size;
+                    entry.units.extend(file_info.unit.clone());
                 }
                 Err(e) => {
                     removal_stats.errors.insert(
@@ -528,22 +1067,15 @@ impl CleanCommand {
             pb.inc(1);
         }
 
-        for dir_info in stats.dirs_to_remove.iter().filter(|_| sel.remove_dirs) {
+        for dir_info in stats.dirs_to_remove.iter().filter(|d| sel.contains(d.kind)) {
             match fs::remove_dir_all(&dir_info.path).await {
                 Ok(_) => {
-                    removal_stats.files += 1;
-                    removal_stats.bytes += dir_info.size;
-                    let profile_entry = removal_stats
-                        .per_profile
-                        .entry(dir_info.profile.clone())
-                        .or_default();
-                    profile_entry.files += 1;
-                    profile_entry.bytes += dir_info.size;
+                    removal_stats.add_reclaimable(&dir_info.profile, dir_info.size, dir_info.sizes);
                 }
                 Err(e) => {
                     removal_stats.errors.insert(
                         (
-                            String::from("incremental"),
+                            dir_info.kind.tag().to_string(),
                             dir_info.profile.clone(),
                             dir_info.path.display().to_string(),
                         ),
@@ -559,12 +1091,67 @@ impl CleanCommand {
         Ok(removal_stats)
     }
 
+    /// Warn that `reason` points at a target directory other workspaces may
+    /// share, and stop unless `--allow-shared-target-dir` was given.
+    fn confirm_shared_target(&self, reason: &str, detected: &str) -> Result<()> {
+        let color = std::io::stderr().is_terminal();
+        let warn_style = Style::new().fg_color(Some(AnsiColor::Yellow.into())).bold();
+        let accent_style = Style::new().fg_color(Some(AnsiColor::Cyan.into()));
+        let block_style = Style::new().fg_color(Some(AnsiColor::Magenta.into()));
+        let stop_style = Style::new().fg_color(Some(AnsiColor::Red.into())).bold();
+
+        eprintln!(
+            "{} {}",
+            paint(color, "Warning:", warn_style),
+            paint(color, reason, accent_style)
+        );
+        eprintln!(
+            "{}",
+            paint(
+                color,
+                "Cleaning a shared/global target may remove artifacts of other workspaces.",
+                block_style
+            )
+        );
+        if self.allow_shared_target_dir {
+            eprintln!(
+                "{} {}",
+                paint(color, "Proceeding because", accent_style),
+                paint(color, "--allow-shared-target-dir was set.", warn_style)
+            );
+            Ok(())
+        } else {
+            eprintln!(
+                "{}",
+                paint(
+                    color,
+                    "Refusing to proceed without --allow-shared-target-dir.",
+                    stop_style
+                )
+            );
+            anyhow::bail!("{detected}; re-run with --allow-shared-target-dir to continue")
+        }
+    }
+
     pub async fn run(self) -> Result<()> {
+        // Reject an unknown report format before spending time on a build
+        if let Some(path) = &self.report {
+            report::ReportFormat::from_path(path)?;
+        }
+
         // Resolve the build command (interactive picker when -c is absent on a TTY)
         let resolved_cmd: Option<String> = if self.custom_command.is_some() {
             self.custom_command.clone()
+        } else if self.trace_log.is_some() || self.stale_toolchains || self.no_trace {
+            // A replay or trace-free planning: nothing to run
+            None
         } else {
-            match select_command_interactive()? {
+            let picked = if self.ci {
+                None
+            } else {
+                select_command_interactive()?
+            };
+            match picked {
                 Some(cmd) => Some(cmd),
                 None => {
                     eprintln!(
@@ -582,55 +1169,18 @@ impl CleanCommand {
                     eprintln!("  cargo-clean-artifact -c 'mise run my-build-task'");
                     eprintln!();
                     eprintln!("For more information, try '\x1b[1m--help\x1b[0m'.");
-                    std::process::exit(2);
+                    std::process::exit(ci::EXIT_USAGE);
                 }
             }
         };
-        let build_cmd = resolved_cmd.expect("command must be set");
+        let build_cmd = resolved_cmd;
+        let started = std::time::Instant::now();
 
         if let Ok(target_dir) = env::var("CARGO_TARGET_DIR") {
-            let color = std::io::stderr().is_terminal();
-            let warn_style = Style::new().fg_color(Some(AnsiColor::Yellow.into())).bold();
-            let accent_style = Style::new().fg_color(Some(AnsiColor::Cyan.into()));
-            let block_style = Style::new().fg_color(Some(AnsiColor::Magenta.into()));
-            let stop_style = Style::new().fg_color(Some(AnsiColor::Red.into())).bold();
-
-            eprintln!(
-                "{} {}",
-                paint(color, "Warning:", warn_style),
-                paint(
-                    color,
-                    format!("CARGO_TARGET_DIR is set to {target_dir}"),
-                    accent_style
-                )
-            );
-            eprintln!(
-                "{}",
-                paint(
-                    color,
-                    "Cleaning a shared/global target may remove artifacts of other workspaces.",
-                    block_style
-                )
-            );
-            if self.allow_shared_target_dir {
-                eprintln!(
-                    "{} {}",
-                    paint(color, "Proceeding because", accent_style),
-                    paint(color, "--allow-shared-target-dir was set.", warn_style)
-                );
-            } else {
-                eprintln!(
-                    "{}",
-                    paint(
-                        color,
-                        "Refusing to proceed without --allow-shared-target-dir.",
-                        stop_style
-                    )
-                );
-                anyhow::bail!(
-                    "CARGO_TARGET_DIR detected; re-run with --allow-shared-target-dir to continue"
-                );
-            }
+            self.confirm_shared_target(
+                &format!("CARGO_TARGET_DIR is set to {target_dir}"),
+                "CARGO_TARGET_DIR detected",
+            )?;
         }
 
         // todo: recursively find all git projects in the directory
@@ -638,7 +1188,7 @@ impl CleanCommand {
 
         let remove_unused_files = async {
             let stats = try_join_all(dirs.iter().map(async |dir| {
-                self.remove_unused_files_of_cargo(dir.as_path(), &build_cmd)
+                self.remove_unused_files_of_cargo(dir.as_path(), build_cmd.as_deref())
                     .await
                     .with_context(|| {
                         format!("failed to clean up unused files in {}", dir.display())
@@ -657,38 +1207,112 @@ impl CleanCommand {
             Ok::<_, anyhow::Error>(total)
         };
 
-        let total_stats = remove_unused_files.await?;
+        let total_stats = match remove_unused_files.await {
+            Ok(stats) => stats,
+            Err(e) if self.ci && e.downcast_ref::<TraceFailed>().is_some() => {
+                eprintln!("\x1b[1;31merror\x1b[0m: {e:#}");
+                std::process::exit(ci::EXIT_TRACE_FAILED);
+            }
+            Err(e) => return Err(e),
+        };
 
         // Always show the per-profile size breakdown
         print_profile_breakdown(&total_stats);
+        print_toolchain_breakdown(&total_stats);
+        print_estimate_notice(&total_stats);
+        print_unclassified(&total_stats);
 
-        if total_stats.files == 0 {
-            println!("✨ No unused artifacts found! Your target directory is already clean.");
-            return Ok(());
+        if let Some(path) = &self.report {
+            report::write_report(path, &total_stats)?;
+            println!("📝 Report written to {}", path.display());
+            println!();
         }
 
-        // Show detailed summary of what will be removed
-        print_detailed_summary(&total_stats);
-
-        // Interactive confirmation if not in --yes mode
-        let selection = if !self.yes {
-            prompt_step_by_step(&total_stats)?
-        } else if !self.dry_run {
-            RemovalSelection {
-                remove_files: true,
-                remove_dirs: true,
-            }
+        let removed = if total_stats.files == 0 {
+            println!("✨ No unused artifacts found! Your target directory is already clean.");
+            None
+        } else if self.ci {
+            // --ci: never prompt; remove only with -y
+            confirm_and_remove(&total_stats, true, self.dry_run || !self.yes).await?
         } else {
-            RemovalSelection::default()
+            confirm_and_remove(&total_stats, self.yes, self.dry_run).await?
         };
 
-        if selection.any() {
-            let removal_stats = self.actually_remove_files(&total_stats, &selection).await?;
-            print_removal_summary(&removal_stats);
-        } else {
-            print_dry_run_summary(&total_stats);
+        let workspace = std::path::absolute(&self.dir).unwrap_or_else(|_| self.dir.clone());
+        if !self.no_ledger
+            && let Some(path) = ledger::default_path()
+        {
+            let record = ledger::LedgerRecord::new(
+                workspace.clone(),
+                build_cmd.iter().cloned().collect(),
+                &total_stats,
+                removed.as_ref(),
+                started.elapsed(),
+            );
+            if let Err(e) = ledger::append(&path, &record) {
+                log::warn!("Failed to record the run in the ledger: {e:#}");
+            }
         }
 
+        if let Some(path) = &self.metrics_file {
+            metrics::write_metrics(path, &workspace, &total_stats, removed.as_ref())?;
+        }
+
+        if !self.ci {
+            return Ok(());
+        }
+        let code = ci::exit_code(&total_stats, removed.as_ref(), self.fail_if_reclaimable);
+        if code != ci::EXIT_CLEAN {
+            eprintln!(
+                "{}",
+                ci::explain(code, &total_stats, self.fail_if_reclaimable)
+            );
+            std::process::exit(code);
+        }
         Ok(())
     }
 }
+
+/// Warn when `target_dir` was never built into; there is nothing to clean.
+fn missing_target_dir(target_dir: &Path) -> bool {
+    if target_dir.exists() {
+        return false;
+    }
+    eprintln!(
+        "⚠️  Warning: Target directory does not exist: {}",
+        target_dir.display()
+    );
+    eprintln!("   Run your build command first to generate build artifacts.");
+    true
+}
+
+/// Shared tail of every clean flow: summarize the plan, ask (unless `--yes`),
+/// remove the selected kinds and print the outcome.
+///
+/// Returns the removal outcome, or `None` when nothing was removed (dry-run).
+async fn confirm_and_remove(
+    total_stats: &CleanupStats,
+    yes: bool,
+    dry_run: bool,
+) -> Result<Option<CleanupStats>> {
+    // Show detailed summary of what will be removed
+    print_detailed_summary(total_stats);
+
+    // Interactive confirmation if not in --yes mode
+    let selection = if !yes {
+        prompt_step_by_step(total_stats)?
+    } else if !dry_run {
+        RemovalSelection::everything(total_stats)
+    } else {
+        RemovalSelection::default()
+    };
+
+    if selection.any() {
+        let removal_stats = CleanCommand::actually_remove_files(total_stats, &selection).await?;
+        print_removal_summary(&removal_stats);
+        Ok(Some(removal_stats))
+    } else {
+        print_dry_run_summary(total_stats);
+        Ok(None)
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeSet;
 use std::io::{self, IsTerminal, Write};
 
 use anstyle::{AnsiColor, Style};
@@ -6,18 +7,28 @@ use console::{Key, Term};
 
 use crate::crate_deps::{format_bytes, paint};
 
-use super::stats::CleanupStats;
+use super::stats::{CleanupStats, RemovalKind};
 
 /// Which categories the user chose to remove in the step-by-step prompt.
 #[derive(Default)]
 pub(super) struct RemovalSelection {
-    pub(super) remove_files: bool,
-    pub(super) remove_dirs: bool,
+    kinds: BTreeSet<RemovalKind>,
 }
 
 impl RemovalSelection {
+    /// Select every kind that has something planned (`--yes`).
+    pub(super) fn everything(stats: &CleanupStats) -> Self {
+        Self {
+            kinds: stats.removal_kinds().into_iter().collect(),
+        }
+    }
+
     pub(super) fn any(&self) -> bool {
-        self.remove_files || self.remove_dirs
+        !self.kinds.is_empty()
+    }
+
+    pub(super) fn contains(&self, kind: RemovalKind) -> bool {
+        self.kinds.contains(&kind)
     }
 }
 
@@ -149,81 +160,79 @@ pub(super) fn prompt_step_by_step(stats: &CleanupStats) -> Result<RemovalSelecti
     let dim_style = Style::new().fg_color(Some(AnsiColor::BrightBlack.into()));
     let mut sel = RemovalSelection::default();
 
-    // ── Step 1: stale artifact files ─────────────────────────────────────────
-    if !stats.files_to_remove.is_empty() {
-        let files_bytes: u64 = stats.files_to_remove.iter().map(|f| f.size).sum();
-        let prompt = format!(
-            "{} Remove {} stale artifact files ({})? [y/N]: ",
-            paint(color, "❯", prompt_style),
-            paint(color, stats.files_to_remove.len().to_string(), size_style),
-            paint(color, format_bytes(files_bytes), size_style),
-        );
-        sel.remove_files = ask_yes_no(&prompt)?;
-    }
-
-    // ── Step 2: stale incremental dirs ───────────────────────────────────────
-    if !stats.dirs_to_remove.is_empty() {
-        // Show top stale incremental dirs sorted by size
-        let mut sorted_dirs = stats.dirs_to_remove.clone();
-        sorted_dirs.sort_by_key(|d| std::cmp::Reverse(d.size));
-        let dirs_bytes: u64 = sorted_dirs.iter().map(|d| d.size).sum();
-
-        println!();
-        println!();
-        println!(
-            "{}",
-            paint(color, "🗂  Stale incremental sessions:", Style::new().bold())
-        );
-        let show_n = 5.min(sorted_dirs.len());
-        for dir in sorted_dirs.iter().take(show_n) {
-            let name = dir.path.file_name().and_then(|n| n.to_str()).unwrap_or("?");
-            println!(
-                "  {}  {} {}",
-                paint(
-                    color,
-                    "🗑",
-                    Style::new().fg_color(Some(AnsiColor::Red.into()))
-                ),
-                paint(color, name, dim_style),
-                paint(color, format!("({})", format_bytes(dir.size)), size_style),
-            );
-        }
-        if sorted_dirs.len() > show_n {
-            println!(
-                "  {}",
-                paint(
-                    color,
-                    format!("… and {} more stale sessions", sorted_dirs.len() - show_n),
-                    dim_style
-                )
-            );
+    // ── One step per kind: files first (already listed in the summary), then
+    //    directory kinds with their own short listing ────────────────────────
+    for kind in stats.removal_kinds() {
+        let files: Vec<_> = stats
+            .files_to_remove
+            .iter()
+            .filter(|f| f.kind == kind)
+            .collect();
+        let mut dirs: Vec<_> = stats
+            .dirs_to_remove
+            .iter()
+            .filter(|d| d.kind == kind)
+            .collect();
+        let count = files.len() + dirs.len();
+        let bytes: u64 =
+            files.iter().map(|f| f.size).sum::<u64>() + dirs.iter().map(|d| d.size).sum::<u64>();
+
+        if !dirs.is_empty() {
+            // Show top dirs of this kind sorted by size
+            dirs.sort_by_key(|d| std::cmp::Reverse(d.size));
+            let (heading, more_noun) = kind.listing();
+
+            println!();
+            println!();
+            println!("{}", paint(color, heading, Style::new().bold()));
+            let show_n = 5.min(dirs.len());
+            for dir in dirs.iter().take(show_n) {
+                let name = dir.display_name();
+                println!(
+                    "  {}  {} {}",
+                    paint(
+                        color,
+                        "🗑",
+                        Style::new().fg_color(Some(AnsiColor::Red.into()))
+                    ),
+                    paint(color, name, dim_style),
+                    paint(color, format!("({})", format_bytes(dir.size)), size_style),
+                );
+            }
+            if dirs.len() > show_n {
+                println!(
+                    "  {}",
+                    paint(
+                        color,
+                        format!("… and {} more {more_noun}", dirs.len() - show_n),
+                        dim_style
+                    )
+                );
+            }
         }
 
         let prompt = format!(
-            "{} Remove {} stale incremental dirs ({})? [y/N]: ",
+            "{} Remove {} {} ({})? [y/N]: ",
             paint(color, "❯", prompt_style),
-            paint(color, sorted_dirs.len().to_string(), size_style),
-            paint(color, format_bytes(dirs_bytes), size_style),
+            paint(color, count.to_string(), size_style),
+            kind.label(),
+            paint(color, format_bytes(bytes), size_style),
         );
-        sel.remove_dirs = ask_yes_no(&prompt)?;
+        if ask_yes_no(&prompt)? {
+            sel.kinds.insert(kind);
+        }
     }
 
     // ── Final combined confirmation ───────────────────────────────────────────
     if sel.any() {
         let mut parts: Vec<String> = Vec::new();
         let mut total_bytes = 0u64;
-        if sel.remove_files {
-            let b: u64 = stats.files_to_remove.iter().map(|f| f.size).sum();
-            parts.push(format!("{} files", stats.files_to_remove.len()));
-            total_bytes += b;
-        }
-        if sel.remove_dirs {
-            let b: u64 = stats.dirs_to_remove.iter().map(|d| d.size).sum();
-            parts.push(format!(
-                "{} stale incremental dirs",
-                stats.dirs_to_remove.len()
-            ));
-            total_bytes += b;
+        for &kind in &sel.kinds {
+            let files = stats.files_to_remove.iter().filter(|f| f.kind == kind);
+            let dirs = stats.dirs_to_remove.iter().filter(|d| d.kind == kind);
+            let count = files.clone().count() + dirs.clone().count();
+            total_bytes += files.map(|f| f.size).sum::<u64>() + dirs.map(|d| d.size).sum::<u64>();
+            parts.push(format!("{count} {}", kind.short_label()));
         }
         let desc = parts.join(" + ");
         let prompt = format!(
@@ -234,8 +243,7 @@ pub(super) fn prompt_step_by_step(stats: &CleanupStats) -> Result<RemovalSelecti
         );
         let confirmed = ask_yes_no(&prompt)?;
         if !confirmed {
-            sel.remove_files = false;
-            sel.remove_dirs = false;
+            sel.kinds.clear();
         }
     }
 
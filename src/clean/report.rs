@@ -0,0 +1,444 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use crate::crate_deps::format_bytes;
+
+use super::stats::{CleanupStats, RemovalKind};
+
+/// Rows shown in the "top crates" and "in-use artifacts" tables.
+const TOP_ROWS: usize = 25;
+
+/// Output format of `--report`, chosen by the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub(super) fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("md" | "markdown") => Ok(ReportFormat::Markdown),
+            Some("html" | "htm") => Ok(ReportFormat::Html),
+            _ => bail!(
+                "cannot tell the report format of {}: use a .md or .html file name",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Write a report of `stats` to `path` in the format its extension names.
+pub(super) fn write_report(path: &Path, stats: &CleanupStats) -> Result<()> {
+    let text = match ReportFormat::from_path(path)? {
+        ReportFormat::Markdown => render_markdown(stats),
+        ReportFormat::Html => render_html(stats),
+    };
+    std::fs::write(path, text).with_context(|| format!("writing report to {}", path.display()))
+}
+
+/// One row of the per-profile table.
+struct ProfileRow<'a> {
+    name: &'a str,
+    kept: u64,
+    reclaimable: u64,
+    /// Reclaimable bytes that are split debuginfo
+    debuginfo: u64,
+    items: usize,
+    total: u64,
+}
+
+fn profile_rows(stats: &CleanupStats) -> Vec<ProfileRow<'_>> {
+    let mut rows: Vec<ProfileRow> = stats
+        .per_profile
+        .iter()
+        .map(|(name, p)| ProfileRow {
+            name,
+            kept: p.used_bytes,
+            reclaimable: p.bytes,
+            debuginfo: p.debuginfo_bytes,
+            items: p.files,
+            total: p.total_dir_bytes,
+        })
+        .collect();
+    rows.sort_by(|a, b| a.name.cmp(b.name));
+    rows
+}
+
+/// `(crate, files, bytes)`, most reclaimable first.
+fn top_crates(stats: &CleanupStats) -> Vec<(&str, usize, u64)> {
+    let mut crates: Vec<(&str, usize, u64)> = stats
+        .per_crate
+        .iter()
+        .map(|(name, c)| (name.as_str(), c.files, c.bytes))
+        .collect();
+    crates.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)));
+    crates.truncate(TOP_ROWS);
+    crates
+}
+
+/// `(name, profile, bytes)` of stale incremental units and `unit/session`s,
+/// largest first.
+fn incremental_sessions(stats: &CleanupStats) -> Vec<(String, &str, u64)> {
+    let mut sessions: Vec<(String, &str, u64)> = stats
+        .dirs_to_remove
+        .iter()
+        .filter(|d| d.kind == RemovalKind::Incremental)
+        .map(|d| (d.display_name(), d.profile.as_str(), d.size))
+        .collect();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.2));
+    sessions
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn headline(stats: &CleanupStats) -> String {
+    let mut line = format!(
+        "Reclaimable: {} ({} items) • In use: {}",
+        format_bytes(stats.bytes),
+        stats.files,
+        format_bytes(stats.used_bytes)
+    );
+    if stats.estimated {
+        line.push_str(" • estimate (planned with --no-trace)");
+    }
+    line
+}
+
+/// Escape a Markdown table cell.
+fn md_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn render_markdown(stats: &CleanupStats) -> String {
+    let mut md = String::new();
+    let _ = writeln!(md, "# cargo-clean-artifact report\n");
+    let _ = writeln!(md, "{}\n", headline(stats));
+
+    let _ = writeln!(md, "## Profiles\n");
+    let _ = writeln!(
+        md,
+        "| Profile | Kept | Reclaimable | Of which debuginfo | Items | Profile dir |"
+    );
+    let _ = writeln!(md, "|---|---:|---:|---:|---:|---:|");
+    for row in profile_rows(stats) {
+        let _ = writeln!(
+            md,
+            "| `{}` | {} | {} | {} | {} | {} |",
+            md_cell(row.name),
+            format_bytes(row.kept),
+            format_bytes(row.reclaimable),
+            format_bytes(row.debuginfo),
+            row.items,
+            format_bytes(row.total)
+        );
+    }
+
+    let _ = writeln!(md, "\n## Top crates by reclaimable bytes\n");
+    let _ = writeln!(md, "| Crate | Files | Reclaimable |");
+    let _ = writeln!(md, "|---|---:|---:|");
+    for (name, files, bytes) in top_crates(stats) {
+        let _ = writeln!(
+            md,
+            "| {} | {files} | {} |",
+            md_cell(name),
+            format_bytes(bytes)
+        );
+    }
+
+    let _ = writeln!(md, "\n## Largest in-use artifacts\n");
+    let _ = writeln!(md, "| Artifact | Profile | Size | Used by |");
+    let _ = writeln!(md, "|---|---|---:|---|");
+    for artifact in stats.in_use.iter().take(TOP_ROWS) {
+        let _ = writeln!(
+            md,
+            "| `{}` | {} | {} | {} |",
+            md_cell(&file_name(&artifact.path)),
+            md_cell(&artifact.profile),
+            format_bytes(artifact.size),
+            md_cell(&artifact.used_by.join(", "))
+        );
+    }
+
+    let _ = writeln!(md, "\n## Stale incremental sessions\n");
+    let sessions = incremental_sessions(stats);
+    if sessions.is_empty() {
+        let _ = writeln!(md, "None.");
+    } else {
+        let _ = writeln!(md, "| Session | Profile | Size |");
+        let _ = writeln!(md, "|---|---|---:|");
+        for (name, profile, bytes) in sessions {
+            let _ = writeln!(
+                md,
+                "| `{}` | {} | {} |",
+                md_cell(&name),
+                md_cell(profile),
+                format_bytes(bytes)
+            );
+        }
+    }
+    md
+}
+
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A table cell whose sort key is the raw byte count.
+fn bytes_cell(bytes: u64) -> String {
+    format!(
+        "<td class=\"num\" data-sort=\"{bytes}\">{}</td>",
+        format_bytes(bytes)
+    )
+}
+
+const HTML_STYLE: &str = "body{font:14px/1.4 system-ui,sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{padding:4px 10px;border-bottom:1px solid #ddd;text-align:left}\
+th{cursor:pointer;background:#f4f4f4;user-select:none}\
+th:after{content:' \\2195';color:#aaa}\
+td.num{text-align:right;font-variant-numeric:tabular-nums}\
+code{font-size:13px}\
+.treemap{display:flex;height:260px;margin-bottom:1em;border:1px solid #999}\
+.profile{display:flex;flex-direction:column;border-right:2px solid #fff;min-width:2px}\
+.profile>div{overflow:hidden;color:#fff;font-size:12px;padding:2px 4px;min-height:0}\
+.kept{background:#3a7d44}.reclaimable{background:#c0392b}.other{background:#7f8c8d}\
+.legend span{display:inline-block;width:12px;height:12px;margin:0 4px 0 12px;vertical-align:middle}";
+
+/// Click a header to sort its table; numeric cells sort by `data-sort`.
+const HTML_SCRIPT: &str = "document.querySelectorAll('th').forEach(function(th){\
+th.addEventListener('click',function(){\
+var table=th.closest('table'),body=table.tBodies[0],i=th.cellIndex;\
+var asc=th.dataset.dir!=='asc';th.dataset.dir=asc?'asc':'desc';\
+var key=function(r){var c=r.cells[i];return c.dataset.sort!==undefined?Number(c.dataset.sort):c.textContent.toLowerCase();};\
+Array.from(body.rows).sort(function(a,b){var x=key(a),y=key(b);return (x<y?-1:x>y?1:0)*(asc?1:-1);})\
+.forEach(function(r){body.appendChild(r);});});});";
+
+fn html_table(html: &mut String, headers: &[&str], rows: &[String]) {
+    html.push_str("<table><thead><tr>");
+    for header in headers {
+        let _ = write!(html, "<th>{header}</th>");
+    }
+    html.push_str("</tr></thead><tbody>\n");
+    for row in rows {
+        let _ = writeln!(html, "<tr>{row}</tr>");
+    }
+    html.push_str("</tbody></table>\n");
+}
+
+/// Profiles side by side, each split into kept, reclaimable and other bytes
+/// (build scripts, fingerprints, outputs, …), areas proportional to size.
+fn html_treemap(html: &mut String, rows: &[ProfileRow]) {
+    html.push_str("<div class=\"treemap\">");
+    for row in rows.iter().filter(|r| r.total > 0) {
+        let other = row.total.saturating_sub(row.kept + row.reclaimable);
+        let _ = write!(
+            html,
+            "<div class=\"profile\" style=\"flex:{} 1 0\" title=\"{} — {}\">",
+            row.total,
+            html_escape(row.name),
+            format_bytes(row.total)
+        );
+        for (class, label, bytes) in [
+            ("kept", "kept", row.kept),
+            ("reclaimable", "reclaimable", row.reclaimable),
+            ("other", "other", other),
+        ] {
+            if bytes > 0 {
+                let _ = write!(
+                    html,
+                    "<div class=\"{class}\" style=\"flex:{bytes} 1 0\" title=\"{} {label}: {}\">{}</div>",
+                    html_escape(row.name),
+                    format_bytes(bytes),
+                    html_escape(row.name)
+                );
+            }
+        }
+        html.push_str("</div>");
+    }
+    html.push_str(
+        "</div>\n<p class=\"legend\"><span class=\"kept\"></span>kept\
+<span class=\"reclaimable\"></span>reclaimable<span class=\"other\"></span>other</p>\n",
+    );
+}
+
+fn render_html(stats: &CleanupStats) -> String {
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+<title>cargo-clean-artifact report</title><style>{HTML_STYLE}</style></head><body>"
+    );
+    let _ = writeln!(html, "<h1>cargo-clean-artifact report</h1>");
+    let _ = writeln!(html, "<p>{}</p>", html_escape(&headline(stats)));
+
+    let profiles = profile_rows(stats);
+    let _ = writeln!(html, "<h2>target/</h2>");
+    html_treemap(&mut html, &profiles);
+
+    let _ = writeln!(html, "<h2>Profiles</h2>");
+    let rows: Vec<String> = profiles
+        .iter()
+        .map(|r| {
+            format!(
+                "<td><code>{}</code></td>{}{}{}<td class=\"num\" data-sort=\"{}\">{}</td>{}",
+                html_escape(r.name),
+                bytes_cell(r.kept),
+                bytes_cell(r.reclaimable),
+                bytes_cell(r.debuginfo),
+                r.items,
+                r.items,
+                bytes_cell(r.total)
+            )
+        })
+        .collect();
+    html_table(
+        &mut html,
+        &[
+            "Profile",
+            "Kept",
+            "Reclaimable",
+            "Of which debuginfo",
+            "Items",
+            "Profile dir",
+        ],
+        &rows,
+    );
+
+    let _ = writeln!(html, "<h2>Top crates by reclaimable bytes</h2>");
+    let rows: Vec<String> = top_crates(stats)
+        .into_iter()
+        .map(|(name, files, bytes)| {
+            format!(
+                "<td>{}</td><td class=\"num\" data-sort=\"{files}\">{files}</td>{}",
+                html_escape(name),
+                bytes_cell(bytes)
+            )
+        })
+        .collect();
+    html_table(&mut html, &["Crate", "Files", "Reclaimable"], &rows);
+
+    let _ = writeln!(html, "<h2>Largest in-use artifacts</h2>");
+    let rows: Vec<String> = stats
+        .in_use
+        .iter()
+        .take(TOP_ROWS)
+        .map(|a| {
+            format!(
+                "<td><code>{}</code></td><td>{}</td>{}<td>{}</td>",
+                html_escape(&file_name(&a.path)),
+                html_escape(&a.profile),
+                bytes_cell(a.size),
+                html_escape(&a.used_by.join(", "))
+            )
+        })
+        .collect();
+    html_table(
+        &mut html,
+        &["Artifact", "Profile", "Size", "Used by"],
+        &rows,
+    );
+
+    let _ = writeln!(html, "<h2>Stale incremental sessions</h2>");
+    let rows: Vec<String> = incremental_sessions(stats)
+        .into_iter()
+        .map(|(name, profile, bytes)| {
+            format!(
+                "<td><code>{}</code></td><td>{}</td>{}",
+                html_escape(&name),
+                html_escape(profile),
+                bytes_cell(bytes)
+            )
+        })
+        .collect();
+    html_table(&mut html, &["Session", "Profile", "Size"], &rows);
+
+    let _ = writeln!(html, "<script>{HTML_SCRIPT}</script>\n</body></html>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::stats::{CrateStat, DirToRemove, InUseArtifact};
+    use std::path::PathBuf;
+
+    fn sample() -> CleanupStats {
+        let mut stats = CleanupStats::default();
+        stats.add_reclaimable("debug", 4096, Default::default());
+        stats.add_reclaimable("debug", 2048, Default::default());
+        let debug = stats.per_profile.get_mut("debug").unwrap();
+        debug.used_bytes = 8192;
+        debug.total_dir_bytes = 20_000;
+        stats.per_crate.insert(
+            "serde".to_string(),
+            CrateStat {
+                files: 1,
+                bytes: 4096,
+                ..Default::default()
+            },
+        );
+        stats.in_use.push(InUseArtifact {
+            path: PathBuf::from("target/debug/deps/libsyn-1.rlib"),
+            profile: "debug".to_string(),
+            size: 8192,
+            used_by: vec!["my<crate>".to_string(), "serde_derive".to_string()],
+        });
+        stats.dirs_to_remove.push(DirToRemove {
+            path: PathBuf::from("target/debug/incremental/app-1abc"),
+            size: 2048,
+            sizes: Default::default(),
+            profile: "debug".to_string(),
+            kind: RemovalKind::Incremental,
+        });
+        stats
+    }
+
+    #[test]
+    fn format_follows_extension() {
+        assert_eq!(
+            ReportFormat::from_path(Path::new("r.md")).unwrap(),
+            ReportFormat::Markdown
+        );
+        assert_eq!(
+            ReportFormat::from_path(Path::new("out/r.html")).unwrap(),
+            ReportFormat::Html
+        );
+        assert!(ReportFormat::from_path(Path::new("r.txt")).is_err());
+    }
+
+    #[test]
+    fn markdown_has_every_section() {
+        let md = render_markdown(&sample());
+        assert!(md.contains("| `debug` | 8.00 KiB | 6.00 KiB | 0 B | 2 | 19.53 KiB |"));
+        assert!(md.contains("| serde | 1 | 4.00 KiB |"));
+        assert!(md.contains("| `libsyn-1.rlib` | debug | 8.00 KiB | my<crate>, serde_derive |"));
+        assert!(md.contains("| `app-1abc` | debug | 2.00 KiB |"));
+    }
+
+    #[test]
+    fn html_is_self_contained_and_escaped() {
+        let html = render_html(&sample());
+        assert!(html.contains("my&lt;crate&gt;, serde_derive"));
+        assert!(html.contains("data-sort=\"4096\""));
+        assert!(html.contains("class=\"treemap\""));
+        assert!(!html.contains("http://") && !html.contains("https://"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Split a shell command into words, honouring single and double quotes and
+/// backslash escapes. Good enough to find cargo's own options in `-c`.
+fn split_words(cmd: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// The value of cargo option `name` (e.g. `--target-dir`) in a traced
+/// command, as `--name VALUE` or `--name=VALUE`. Words after `--` belong to
+/// rustc or the test binary and are not looked at.
+pub(super) fn cargo_option(cmd: &str, name: &str) -> Option<String> {
+    let words = split_words(cmd);
+    let mut words = words.iter().take_while(|w| *w != "--");
+    let mut value = None;
+    while let Some(word) = words.next() {
+        if word == name {
+            value = words.next().cloned();
+        } else if let Some(v) = word
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            value = Some(v.to_string());
+        }
+    }
+    value
+}
+
+/// The absolute path of cargo option `name` of a command run in `dir`, such
+/// as `--target-dir t`, which cargo resolves against its working directory.
+pub(super) fn cargo_path_option(cmd: &str, name: &str, dir: &Path) -> Result<Option<PathBuf>> {
+    cargo_option(cmd, name)
+        .map(|value| std::path::absolute(dir.join(value)))
+        .transpose()
+        .with_context(|| format!("failed to resolve {name} of `{cmd}`"))
+}
+
+/// The cargo target (or build) directory an artifact was written to: the
+/// closest ancestor holding the `CACHEDIR.TAG` cargo creates in both.
+pub(super) fn target_root(artifact: &Path) -> Option<PathBuf> {
+    artifact
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join("CACHEDIR.TAG").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Target roots of the traced artifacts that were not under the expected ones.
+pub(super) fn traced_roots<'a>(
+    artifacts: impl IntoIterator<Item = &'a PathBuf>,
+) -> BTreeSet<PathBuf> {
+    artifacts
+        .into_iter()
+        .filter_map(|a| target_root(a))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn cargo_options_are_found_in_commands() {
+        let cmd = "cargo build --release --target-dir '/tmp/my t' --manifest-path=sub/Cargo.toml";
+        assert_eq!(
+            cargo_option(cmd, "--target-dir").as_deref(),
+            Some("/tmp/my t")
+        );
+        assert_eq!(
+            cargo_option(cmd, "--manifest-path").as_deref(),
+            Some("sub/Cargo.toml")
+        );
+        assert_eq!(cargo_option("cargo build", "--target-dir"), None);
+        // Options of the test binary don't count
+        assert_eq!(
+            cargo_option("cargo test -- --target-dir x", "--target-dir"),
+            None
+        );
+        assert_eq!(
+            split_words(r#"sh -c "a \"b\" c" d\ e"#),
+            ["sh", "-c", r#"a "b" c"#, "d e"]
+        );
+    }
+
+    #[test]
+    fn relative_paths_are_resolved_against_the_command_dir() {
+        let cmd = "cargo build --target-dir t";
+        let resolved = cargo_path_option(cmd, "--target-dir", Path::new("."))
+            .unwrap()
+            .unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(resolved, cwd.join("t"));
+        assert!(resolved.starts_with(&cwd));
+        assert_eq!(
+            cargo_path_option(cmd, "--target-dir", Path::new("/ws/sub")).unwrap(),
+            Some(PathBuf::from("/ws/sub/t"))
+        );
+        assert_eq!(
+            cargo_path_option(
+                "cargo build --target-dir /abs",
+                "--target-dir",
+                Path::new("/ws")
+            )
+            .unwrap(),
+            Some(PathBuf::from("/abs"))
+        );
+        assert_eq!(
+            cargo_path_option("cargo build", "--target-dir", Path::new("/ws")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn roots_are_the_dirs_with_a_cachedir_tag() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("t");
+        let deps = root.join("wasm32-unknown-unknown/debug/deps");
+        fs::create_dir_all(&deps).unwrap();
+        fs::write(
+            root.join("CACHEDIR.TAG"),
+            "Signature: 8a477f597d28d172789f06886806bc55",
+        )
+        .unwrap();
+        let artifact = deps.join("libfoo-1.rlib");
+
+        assert_eq!(target_root(&artifact), Some(root.clone()));
+        assert_eq!(
+            traced_roots([&artifact, &tmp.path().join("elsewhere/libbar-2.rlib")]),
+            BTreeSet::from([root])
+        );
+    }
+}
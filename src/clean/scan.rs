@@ -1,21 +1,350 @@
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-/// Recursively sum the size of all files under `dir` (sync, no extra deps).
-pub(super) fn dir_size_bytes(dir: &Path) -> u64 {
-    let Ok(entries) = std::fs::read_dir(dir) else {
-        return 0;
-    };
-    entries
-        .filter_map(|e| e.ok())
-        .map(|e| {
-            let p = e.path();
-            if p.is_dir() {
-                dir_size_bytes(&p)
+use anyhow::{Context, Result};
+use futures::future::try_join_all;
+
+use crate::crate_deps::crate_key;
+
+/// Apparent and allocated size of a file or directory tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DiskSize {
+    /// Logical length (`metadata.len()`), what `ls -l` reports.
+    pub(crate) apparent: u64,
+    /// Bytes actually allocated on disk (`st_blocks * 512`), what `du` reports.
+    pub(crate) allocated: u64,
+}
+
+impl DiskSize {
+    pub(crate) fn add(&mut self, other: DiskSize) {
+        self.apparent += other.apparent;
+        self.allocated += other.allocated;
+    }
+
+    /// The size in the unit selected by `mode`.
+    pub(crate) fn get(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.apparent,
+            SizeMode::DiskUsage => self.allocated,
+        }
+    }
+}
+
+/// Which of the two [`DiskSize`] measures drives planning and headline numbers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SizeMode {
+    /// Logical file length, like `du --apparent-size`.
+    Apparent,
+    /// Allocated blocks, like plain `du`: what a cleanup actually frees.
+    #[default]
+    DiskUsage,
+}
+
+/// Identity of a file that has more than one hard link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Inode {
+    pub(crate) dev: u64,
+    pub(crate) ino: u64,
+    /// Total number of links to this inode anywhere on the filesystem.
+    pub(crate) nlink: u64,
+}
+
+/// Per-path sizes for every file and directory below the scanned roots.
+///
+/// Each root (a profile directory such as `target/debug/`) is walked exactly
+/// once on a blocking thread, and all roots are walked concurrently.  Planning
+/// and display both read from the cache instead of hitting the filesystem again.
+#[derive(Debug, Default)]
+pub(crate) struct SizeCache {
+    entries: HashMap<PathBuf, DiskSize>,
+    /// Hard-link identity for files with `nlink > 1`.
+    inodes: HashMap<PathBuf, Inode>,
+}
+
+impl SizeCache {
+    /// Walk every root in parallel and cache the size of everything below it.
+    pub(crate) async fn scan(roots: impl IntoIterator<Item = PathBuf>) -> Result<Self> {
+        let walks = roots.into_iter().map(|root| async move {
+            tokio::task::spawn_blocking(move || {
+                let mut walked = Walked::default();
+                walk(&root, &mut walked);
+                walked
+            })
+            .await
+            .context("size scan task panicked")
+        });
+
+        let mut cache = SizeCache::default();
+        for walked in try_join_all(walks).await? {
+            cache.entries.extend(walked.entries);
+            cache.inodes.extend(walked.inodes);
+        }
+        Ok(cache)
+    }
+
+    /// Cached size of a file or directory, if it was under a scanned root.
+    pub(crate) fn get(&self, path: &Path) -> Option<DiskSize> {
+        self.entries.get(path).copied()
+    }
+
+    /// Cached size of `path`, or zero when it was not scanned.
+    pub(crate) fn size_of(&self, path: &Path) -> DiskSize {
+        self.get(path).unwrap_or_default()
+    }
+
+    /// Hard-link identity of `path`, if it is a file with more than one link.
+    pub(crate) fn inode_of(&self, path: &Path) -> Option<Inode> {
+        self.inodes.get(path).copied()
+    }
+}
+
+#[derive(Default)]
+struct Walked {
+    entries: HashMap<PathBuf, DiskSize>,
+    inodes: HashMap<PathBuf, Inode>,
+    seen: HashSet<(u64, u64)>,
+}
+
+/// Recursively record the size of `dir` and everything below it.
+///
+/// Files with several hard links are counted towards directory totals only the
+/// first time their inode is seen, so `deps/foo` and an uplifted `foo` in the
+/// profile dir are not double-counted.  The per-file entry always carries the
+/// file's full size.  Symlinks are not followed.
+fn walk(dir: &Path, walked: &mut Walked) -> DiskSize {
+    let mut total = DiskSize::default();
+    if let Ok(read_dir) = std::fs::read_dir(dir) {
+        for entry in read_dir.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let Ok(meta) = std::fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if meta.is_dir() {
+                total.add(walk(&path, walked));
             } else {
-                p.metadata().map(|m| m.len()).unwrap_or(0)
+                let (size, inode) = file_size(&meta);
+                if inode.is_none_or(|i| walked.seen.insert((i.dev, i.ino))) {
+                    total.add(size);
+                }
+                if let Some(inode) = inode {
+                    walked.inodes.insert(path.clone(), inode);
+                }
+                walked.entries.insert(path, size);
+            }
+        }
+    }
+    walked.entries.insert(dir.to_path_buf(), total);
+    total
+}
+
+/// Size of a single file, plus its inode when it has more than one link.
+#[cfg(unix)]
+fn file_size(meta: &std::fs::Metadata) -> (DiskSize, Option<Inode>) {
+    use std::os::unix::fs::MetadataExt;
+
+    let size = DiskSize {
+        apparent: meta.len(),
+        allocated: meta.blocks() * 512,
+    };
+    let inode = (meta.nlink() > 1).then(|| Inode {
+        dev: meta.dev(),
+        ino: meta.ino(),
+        nlink: meta.nlink(),
+    });
+    (size, inode)
+}
+
+#[cfg(not(unix))]
+fn file_size(meta: &std::fs::Metadata) -> (DiskSize, Option<Inode>) {
+    let size = DiskSize {
+        apparent: meta.len(),
+        allocated: meta.len(),
+    };
+    (size, None)
+}
+
+/// Every `deps/` directory under `target_dir`, with its display profile:
+/// `target/<profile>/deps` and `target/<triple>/<profile>/deps`.
+pub(crate) fn discover_deps_dirs(target_dir: &Path) -> Vec<(PathBuf, String)> {
+    let mut found = Vec::new();
+    let children = |dir: &Path| -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .map(|e| e.path())
+            .collect();
+        dirs.sort();
+        dirs
+    };
+    for level1 in children(target_dir) {
+        let mut candidates = vec![level1.clone()];
+        candidates.extend(children(&level1));
+        for profile_dir in candidates {
+            let deps = profile_dir.join("deps");
+            if deps.is_dir()
+                && let Ok(rel) = profile_dir.strip_prefix(target_dir)
+            {
+                found.push((deps, rel.to_string_lossy().into_owned()));
             }
+        }
+    }
+    found
+}
+
+/// Adjust the allocated size of files about to be removed so hard links are
+/// not over-counted.
+///
+/// All links of an inode must be removed before its blocks are freed: when
+/// every link is in `files`, the first one carries the allocated size and the
+/// rest count as zero; when some link survives elsewhere (e.g. the uplifted
+/// binary in `target/debug/`), none of them frees anything.
+pub(crate) fn dedupe_hard_links<'a>(
+    files: impl IntoIterator<Item = (&'a Path, &'a mut DiskSize)>,
+    cache: &SizeCache,
+) {
+    let mut groups: HashMap<(u64, u64), (Inode, Vec<&'a mut DiskSize>)> = HashMap::new();
+    for (path, size) in files {
+        if let Some(inode) = cache.inode_of(path) {
+            groups
+                .entry((inode.dev, inode.ino))
+                .or_insert_with(|| (inode, Vec::new()))
+                .1
+                .push(size);
+        }
+    }
+    for (inode, sizes) in groups.into_values() {
+        let all_links_removed = sizes.len() as u64 >= inode.nlink;
+        for (i, size) in sizes.into_iter().enumerate() {
+            if i > 0 || !all_links_removed {
+                size.allocated = 0;
+            }
+        }
+    }
+}
+
+/// The `deps/` directories below `target_dir` that traced artifacts live in,
+/// with display names like `debug` or `wasm32-unknown-unknown/wasm-dev`.
+///
+/// Deriving them from the trace handles cross-compilation targets
+/// automatically and leaves untraced profiles alone.
+///
+/// Artifacts of the per-unit build-dir layout live in
+/// `<profile>/build/<package>/<hash>/out/`; their profile is listed with the
+/// `deps/` it lacks, so the parent is still the profile directory.
+pub(crate) fn traced_deps_dirs<'a>(
+    used_artifacts: impl IntoIterator<Item = &'a PathBuf>,
+    target_dir: &Path,
+) -> Vec<(PathBuf, String)> {
+    let mut scan_dirs: Vec<(PathBuf, String)> = Vec::new();
+    for artifact in used_artifacts {
+        let Some(parent) = artifact.parent() else {
+            continue;
+        };
+        let deps_dir = if parent.file_name().is_some_and(|n| n == "deps") {
+            parent.to_path_buf()
+        } else if parent.file_name().is_some_and(|n| n == "out")
+            && let Some(build) = parent.ancestors().nth(3)
+            && build.file_name().is_some_and(|n| n == "build")
+            && let Some(profile_dir) = build.parent()
+        {
+            profile_dir.join("deps")
+        } else {
+            continue;
+        };
+        if deps_dir.starts_with(target_dir) && !scan_dirs.iter().any(|(d, _)| *d == deps_dir) {
+            // Display name: strip target_dir prefix and trailing "/deps"
+            let display = deps_dir
+                .strip_prefix(target_dir)
+                .ok()
+                .and_then(|p| p.parent()) // drop "deps" component
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "unknown".to_string());
+            scan_dirs.push((deps_dir, display));
+        }
+    }
+    scan_dirs
+}
+
+/// Crate names of the final build outputs directly in `profile_dir` (the
+/// linked binary, uplifted `.rlib`, `.wasm`, …), which are not in a trace
+/// since nothing depends on them.
+pub(crate) fn output_crate_names(profile_dir: &Path) -> HashSet<String> {
+    std::fs::read_dir(profile_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter_map(|p| {
+            let name = p.file_stem()?.to_str()?;
+            let name = name.strip_prefix("lib").unwrap_or(name);
+            // Normalize hyphens (final binary names use them; artifacts use _)
+            Some(name.replace('-', "_"))
         })
-        .sum()
+        .collect()
+}
+
+/// Stems of the `keep_last` most recently built units of every crate in
+/// `deps_dir`, by the mtime of their `.rlib` / `.rmeta`.
+pub(crate) fn recent_stems(deps_dir: &Path, keep_last: usize) -> HashSet<String> {
+    // crate → stem → newest library mtime
+    let mut builds: HashMap<String, HashMap<String, SystemTime>> = HashMap::new();
+    for entry in std::fs::read_dir(deps_dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let is_lib = path
+            .extension()
+            .is_some_and(|e| e == "rlib" || e == "rmeta");
+        let Some(stem) = artifact_stem(&path).filter(|_| is_lib) else {
+            continue;
+        };
+        let mtime = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let newest = builds
+            .entry(crate_key(&path))
+            .or_default()
+            .entry(stem)
+            .or_insert(mtime);
+        *newest = (*newest).max(mtime);
+    }
+    builds
+        .into_values()
+        .flat_map(|stems| {
+            let mut stems: Vec<_> = stems.into_iter().collect();
+            stems.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            stems.into_iter().take(keep_last).map(|(stem, _)| stem)
+        })
+        .collect()
+}
+
+/// Extensions of split debuginfo files: unpacked DWARF objects, DWARF
+/// packages and Windows program databases.
+const DEBUGINFO_EXTENSIONS: &[&str] = &["dwo", "dwp", "pdb"];
+
+/// Extensions of directory-shaped debuginfo bundles (macOS `.dSYM`).
+const DEBUG_BUNDLE_EXTENSIONS: &[&str] = &["dSYM"];
+
+/// Whether `path` is a directory-shaped debuginfo bundle such as
+/// `foo-HASH.dSYM/`, which belongs to the unit with the same stem.
+pub(crate) fn is_debug_bundle(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| DEBUG_BUNDLE_EXTENSIONS.contains(&e))
+}
+
+/// Whether `path` holds split debuginfo (`split-debuginfo = "unpacked"` or
+/// `"packed"`) rather than code.
+pub(crate) fn is_split_debuginfo(path: &Path) -> bool {
+    is_debug_bundle(path)
+        || path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| DEBUGINFO_EXTENSIONS.contains(&e))
 }
 
 /// Extract the `crate_name-HASH` stem from any artifact file:
@@ -24,6 +353,8 @@ pub(super) fn dir_size_bytes(dir: &Path) -> u64 {
 /// - `foo-HASH.d`                    → `foo-HASH`
 /// - `foo-HASH.foo.cgu.00.rcgu.dwo`  → `foo-HASH`
 /// - `foo-HASH.foo.cgu.00.rcgu.o`    → `foo-HASH`
+/// - `foo-HASH.dwp` / `foo-HASH.pdb` → `foo-HASH`
+/// - `foo-HASH.dSYM` (a directory)   → `foo-HASH`
 pub(super) fn artifact_stem(path: &Path) -> Option<String> {
     let filename = path.file_name()?.to_str()?;
     // Strip "lib" prefix (rlib/rmeta files carry it, dwo/o/d don't)
@@ -43,7 +374,6 @@ mod tests {
     use super::*;
     use std::fs;
     use std::path::Path;
-    use std::time::{Duration, SystemTime};
 
     // ── artifact_stem ─────────────────────────────────────────────────────────
 
@@ -94,97 +424,223 @@ mod tests {
         assert!(!s.starts_with("lib"));
     }
 
-    // ── clean_incremental_dir ─────────────────────────────────────────────────
-
-    /// Helper: create a directory and touch its mtime `offset` seconds in the past.
-    fn make_session(base: &Path, name: &str, age_secs: u64) {
-        let dir = base.join(name);
-        fs::create_dir_all(&dir).unwrap();
-        // Write a dummy file so the dir has content
-        fs::write(dir.join("data"), vec![0u8; 1024]).unwrap();
-        // Set mtime to `age_secs` seconds ago
-        let mtime = SystemTime::now() - Duration::from_secs(age_secs);
-        filetime::set_file_mtime(&dir, filetime::FileTime::from_system_time(mtime)).ok(); // ignore if filetime crate unavailable; mtime ordering still works
+    #[test]
+    fn artifact_stem_debuginfo_layouts() {
+        for name in [
+            "foo-abc.dwp",
+            "foo-abc.pdb",
+            "foo-abc.dSYM",
+            "foo-abc.foo.a1b2c3-cgu.0.rcgu.dwo",
+        ] {
+            assert_eq!(artifact_stem(Path::new(name)), Some("foo-abc".to_string()));
+            assert!(is_split_debuginfo(Path::new(name)), "{name}");
+        }
+        assert!(is_debug_bundle(Path::new("foo-abc.dSYM")));
+        assert!(!is_split_debuginfo(Path::new("libfoo-abc.rlib")));
     }
 
+    // ── clean_with_trace_result ───────────────────────────────────────────────
+
     #[tokio::test]
-    async fn clean_incremental_keeps_newest_session() {
+    async fn clean_with_trace_groups_split_debuginfo_with_unit() {
         let tmp = tempfile::tempdir().unwrap();
-        let inc = tmp.path().join("incremental");
-        fs::create_dir_all(&inc).unwrap();
+        let deps = tmp.path().join("debug/deps");
+        for bundle in ["foo-aaa.dSYM", "foo-bbb.dSYM"] {
+            let dwarf = deps.join(bundle).join("Contents/Resources/DWARF");
+            fs::create_dir_all(&dwarf).unwrap();
+            fs::write(dwarf.join("foo"), vec![0u8; 300]).unwrap();
+        }
+        for file in [
+            "libfoo-aaa.rlib",
+            "foo-aaa.foo.cgu.0.rcgu.dwo",
+            "libfoo-bbb.rlib",
+            "foo-bbb.foo.cgu.0.rcgu.dwo",
+            "foo-bbb.dwp",
+        ] {
+            fs::write(deps.join(file), vec![0u8; 100]).unwrap();
+        }
+        let used = [deps.join("libfoo-aaa.rlib")].into_iter().collect();
 
-        // Three sessions for "bevy_pbr", oldest → newest
-        make_session(&inc, "bevy_pbr-1aaaaaaaaaaaa", 300); // oldest
-        make_session(&inc, "bevy_pbr-2bbbbbbbbbbb", 200);
-        make_session(&inc, "bevy_pbr-3ccccccccccc", 10); // newest
+        let sizes = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+        let stats = super::super::CleanCommand::clean_with_trace_result(
+            &deps,
+            &used,
+            &HashSet::new(),
+            "debug",
+            &sizes,
+            SizeMode::Apparent,
+            0,
+        )
+        .await
+        .unwrap();
 
-        // One session for "serde" (should not be removed)
-        make_session(&inc, "serde-4ddddddddddd", 150);
+        let mut removed: Vec<String> = stats
+            .files_to_remove
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        removed.sort();
+        assert_eq!(
+            removed,
+            [
+                "foo-bbb.dwp",
+                "foo-bbb.foo.cgu.0.rcgu.dwo",
+                "libfoo-bbb.rlib"
+            ]
+        );
+        assert_eq!(stats.dirs_to_remove.len(), 1);
+        assert_eq!(stats.dirs_to_remove[0].path, deps.join("foo-bbb.dSYM"));
+        assert_eq!(stats.bytes, 600);
+        // The old unit's .dwo, .dwp and .dSYM, but not its .rlib
+        assert_eq!(stats.debuginfo_bytes, 500);
+        assert_eq!(stats.per_profile["debug"].debuginfo_bytes, 500);
+        assert_eq!(stats.used_bytes, 500);
+    }
 
-        let stats = super::super::CleanCommand::clean_incremental_dir(tmp.path(), "debug")
-            .await
-            .unwrap();
+    #[tokio::test]
+    async fn keep_last_retains_recent_builds_besides_traced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let deps = tmp.path().join("debug/deps");
+        fs::create_dir_all(&deps).unwrap();
+        for (i, hash) in ["aaa", "bbb", "ccc", "ddd"].into_iter().enumerate() {
+            for file in [format!("libfoo-{hash}.rlib"), format!("foo-{hash}.d")] {
+                let path = deps.join(file);
+                fs::write(&path, vec![0u8; 100]).unwrap();
+                let mtime = filetime::FileTime::from_unix_time(1_000 * (i as i64 + 1), 0);
+                filetime::set_file_mtime(&path, mtime).unwrap();
+            }
+        }
+        // The traced build is the oldest one
+        let used = [deps.join("libfoo-aaa.rlib")].into_iter().collect();
 
-        // Should mark 2 stale bevy_pbr sessions for removal (keep the newest)
         assert_eq!(
-            stats.dirs_to_remove.len(),
-            2,
-            "dirs_to_remove: {:?}",
-            stats
-                .dirs_to_remove
-                .iter()
-                .map(|d| &d.path)
-                .collect::<Vec<_>>()
+            recent_stems(&deps, 2),
+            HashSet::from(["foo-ddd".to_string(), "foo-ccc".to_string()])
         );
+        let sizes = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+        let stats = super::super::CleanCommand::clean_with_trace_result(
+            &deps,
+            &used,
+            &HashSet::new(),
+            "debug",
+            &sizes,
+            SizeMode::Apparent,
+            2,
+        )
+        .await
+        .unwrap();
 
-        // Newest bevy_pbr should NOT be in the list
-        let removed_names: Vec<_> = stats
-            .dirs_to_remove
-            .iter()
-            .map(|d| d.path.file_name().unwrap().to_str().unwrap().to_string())
-            .collect();
-        assert!(
-            !removed_names.iter().any(|n| n.contains("3ccccccccccc")),
-            "newest session should be kept, got: {removed_names:?}"
-        );
-        assert!(
-            removed_names.iter().any(|n| n.contains("1aaaaaaaaaaaa")),
-            "oldest session should be removed"
-        );
+        let removed: Vec<_> = stats.files_to_remove.iter().map(|f| &f.path).collect();
+        assert_eq!(removed.len(), 2);
         assert!(
-            removed_names.iter().any(|n| n.contains("2bbbbbbbbbbb")),
-            "middle session should be removed"
+            removed
+                .iter()
+                .all(|p| p.to_string_lossy().contains("foo-bbb"))
         );
+        assert_eq!(stats.used_bytes, 200);
+        assert_eq!(stats.retained_builds, 2);
+        assert_eq!(stats.retained_bytes, 400);
+        assert_eq!(stats.per_profile["debug"].retained_bytes, 400);
+    }
 
-        // Single-session crate should never be touched
-        assert!(
-            !removed_names.iter().any(|n| n.contains("serde")),
-            "single-session crate should not be removed"
-        );
+    // ── SizeCache ─────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn size_cache_sums_nested_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let nested = tmp.path().join("deps/nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(tmp.path().join("deps/a"), vec![0u8; 100]).unwrap();
+        fs::write(nested.join("b"), vec![0u8; 50]).unwrap();
+
+        let cache = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+
+        assert_eq!(cache.size_of(&nested.join("b")).apparent, 50);
+        assert_eq!(cache.size_of(&nested).apparent, 50);
+        assert_eq!(cache.size_of(&tmp.path().join("deps")).apparent, 150);
+        assert_eq!(cache.size_of(tmp.path()).apparent, 150);
+        assert_eq!(cache.get(Path::new("/not/scanned")), None);
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn clean_incremental_single_session_untouched() {
+    async fn size_cache_counts_hard_links_once() {
         let tmp = tempfile::tempdir().unwrap();
-        let inc = tmp.path().join("incremental");
-        fs::create_dir_all(&inc).unwrap();
-        make_session(&inc, "my_crate-1aaaaaaaaaaaa", 100);
+        let deps = tmp.path().join("deps");
+        fs::create_dir_all(&deps).unwrap();
+        fs::write(deps.join("foo-abc"), vec![0u8; 4096]).unwrap();
+        fs::hard_link(deps.join("foo-abc"), tmp.path().join("foo")).unwrap();
 
-        let stats = super::super::CleanCommand::clean_incremental_dir(tmp.path(), "debug")
-            .await
-            .unwrap();
+        let cache = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
 
-        assert!(stats.dirs_to_remove.is_empty());
-        assert_eq!(stats.bytes, 0);
+        // Each link reports the full file size …
+        assert_eq!(cache.size_of(&deps.join("foo-abc")).apparent, 4096);
+        assert_eq!(cache.size_of(&tmp.path().join("foo")).apparent, 4096);
+        // … but the profile dir total counts the shared inode only once.
+        assert_eq!(cache.size_of(tmp.path()).apparent, 4096);
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn clean_incremental_empty_dir() {
+    async fn size_cache_sparse_file_allocates_less_than_apparent() {
         let tmp = tempfile::tempdir().unwrap();
-        // No incremental/ dir at all
-        let stats = super::super::CleanCommand::clean_incremental_dir(tmp.path(), "debug")
-            .await
-            .unwrap();
-        assert!(stats.dirs_to_remove.is_empty());
+        let sparse = tmp.path().join("sparse.rmeta");
+        let file = fs::File::create(&sparse).unwrap();
+        file.set_len(64 * 1024 * 1024).unwrap();
+
+        let cache = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+        let size = cache.size_of(&sparse);
+
+        assert_eq!(size.apparent, 64 * 1024 * 1024);
+        assert!(size.allocated < size.apparent, "{size:?}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn dedupe_hard_links_only_frees_when_all_links_go() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a");
+        let b = tmp.path().join("b");
+        let c = tmp.path().join("c");
+        fs::write(&a, vec![1u8; 8192]).unwrap();
+        fs::hard_link(&a, &b).unwrap();
+        fs::write(&c, vec![1u8; 8192]).unwrap();
+        fs::hard_link(&c, tmp.path().join("c-kept")).unwrap();
+
+        let cache = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+        let mut planned = [
+            (a.clone(), cache.size_of(&a)),
+            (b.clone(), cache.size_of(&b)),
+            (c.clone(), cache.size_of(&c)),
+        ];
+        dedupe_hard_links(planned.iter_mut().map(|(p, s)| (p.as_path(), s)), &cache);
+
+        let freed: u64 = planned.iter().map(|(_, s)| s.allocated).sum();
+        assert_eq!(freed, cache.size_of(&a).allocated, "{planned:?}");
+        // Apparent sizes are left untouched.
+        assert!(planned.iter().all(|(_, s)| s.apparent == 8192));
+    }
+
+    #[test]
+    fn discover_deps_dirs_finds_native_and_cross_profiles() {
+        let tmp = tempfile::tempdir().unwrap();
+        for dir in [
+            "debug/deps",
+            "release/deps",
+            "wasm32-unknown-unknown/wasm-dev/deps",
+            "debug/build/foo-123",
+            ".cargo-clean-artifact/deps",
+        ] {
+            fs::create_dir_all(tmp.path().join(dir)).unwrap();
+        }
+
+        let found: Vec<String> = discover_deps_dirs(tmp.path())
+            .into_iter()
+            .map(|(_, profile)| profile)
+            .collect();
+        assert_eq!(
+            found,
+            vec!["debug", "release", "wasm32-unknown-unknown/wasm-dev"]
+        );
     }
 }
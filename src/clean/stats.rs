@@ -1,69 +1,324 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::scan::DiskSize;
 
 #[derive(Default)]
 pub(crate) struct CleanupStats {
     pub(crate) files: usize,
+    /// Reclaimable bytes in the selected size mode
     pub(crate) bytes: u64,
+    /// Reclaimable bytes by logical file length
+    pub(crate) apparent_bytes: u64,
+    /// Reclaimable bytes by allocated blocks (hard links de-duplicated)
+    pub(crate) disk_bytes: u64,
     /// Total size of artifacts kept (in use)
     pub(crate) used_bytes: u64,
+    /// Untraced builds kept by `--keep-last`, not counted in `used_bytes`
+    pub(crate) retained_builds: usize,
+    pub(crate) retained_bytes: u64,
+    /// Part of `bytes` that is split debuginfo (`.dwo`, `.dwp`, `.pdb`, `.dSYM/`)
+    pub(crate) debuginfo_bytes: u64,
     pub(crate) per_crate: HashMap<String, CrateStat>,
     pub(crate) per_profile: HashMap<String, ProfileStat>,
+    /// All `deps/` files (kept or not) grouped by the rustc that built them
+    pub(crate) per_toolchain: HashMap<String, CrateStat>,
+    /// The rustc currently selected for the workspace, when known
+    pub(crate) current_toolchain: Option<String>,
     pub(crate) errors: HashMap<(String, String, String), anyhow::Error>,
     pub(crate) files_to_remove: Vec<FileToRemove>,
-    /// Stale incremental compilation session directories to remove
+    /// Directories to remove as a whole (incremental sessions, checkouts, …)
     pub(crate) dirs_to_remove: Vec<DirToRemove>,
+    /// Planned by the `--no-trace` heuristic rather than from a traced build
+    pub(crate) estimated: bool,
+    /// Crates found in `deps/` that are no longer in the resolve (`--no-trace`)
+    pub(crate) unlocked_crates: BTreeSet<String>,
+    /// Traced artifacts that are kept, largest first
+    pub(crate) in_use: Vec<InUseArtifact>,
+    /// Distinct artifacts referenced by the traced build
+    pub(crate) traced_artifacts: usize,
+    /// How long the traced build took (`None` when nothing was traced)
+    pub(crate) trace_duration: Option<Duration>,
+    /// `deps/` files without a `crate-HASH` stem; never removed
+    pub(crate) unclassified: Vec<UnclassifiedFile>,
+}
+
+/// A `deps/` file that can't be tied to a unit by its name.
+#[derive(Debug, Clone)]
+pub(crate) struct UnclassifiedFile {
+    pub(crate) path: PathBuf,
+    pub(crate) profile: String,
+    /// Size in the selected size mode
+    pub(crate) size: u64,
+    pub(crate) action: UnclassifiedAction,
+}
+
+/// What the unclassified-file rules decided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum UnclassifiedAction {
+    /// Kept (and counted as in use), with the reason
+    Keep(String),
+    /// Listed so it can be looked at, but left alone
+    Report,
+}
+
+/// A traced artifact that is kept, with the crates that referenced it.
+#[derive(Clone)]
+pub(crate) struct InUseArtifact {
+    pub(crate) path: PathBuf,
+    pub(crate) profile: String,
+    /// Size in the selected size mode
+    pub(crate) size: u64,
+    /// Sorted names of the crates whose build referenced this artifact
+    pub(crate) used_by: Vec<String>,
 }
 
 #[derive(Default, Clone)]
 pub(crate) struct CrateStat {
     pub(crate) files: usize,
     pub(crate) bytes: u64,
+    /// Labels of the units behind the files, e.g. `serde 1.0.190 [std] release`
+    pub(crate) units: BTreeSet<String>,
 }
 
 #[derive(Default, Clone)]
 pub(crate) struct ProfileStat {
     pub(crate) files: usize,
     pub(crate) bytes: u64,
+    pub(crate) apparent_bytes: u64,
+    pub(crate) disk_bytes: u64,
     /// Bytes in deps/ that are kept (in-use)
     pub(crate) used_bytes: u64,
+    /// Bytes in deps/ of untraced builds kept by `--keep-last`
+    pub(crate) retained_bytes: u64,
+    /// Reclaimable bytes that are split debuginfo
+    pub(crate) debuginfo_bytes: u64,
     /// Total bytes in the entire profile directory (deps + incremental + build + …)
     pub(crate) total_dir_bytes: u64,
 }
 
+/// What a planned removal is; decides its prompt step and how it is labelled.
+///
+/// The declaration order is the order in which prompt steps are asked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum RemovalKind {
+    /// Untraced files in a `deps/` directory
+    Artifact,
+    /// `deps/` files built by a rustc other than the current one
+    OtherToolchain,
+    /// Stale incremental compilation sessions
+    Incremental,
+    /// Untraced profile or target-triple directories that have gone cold
+    ColdProfile,
+    /// `.fingerprint/<unit>` dirs of removed artifacts (`ci-cache --fingerprints`)
+    Fingerprint,
+    /// `.crate` archives in `$CARGO_HOME/registry/cache`
+    RegistryCache,
+    /// Extracted crate sources in `$CARGO_HOME/registry/src`
+    RegistrySource,
+    /// Git checkouts in `$CARGO_HOME/git/checkouts`
+    GitCheckout,
+}
+
+impl RemovalKind {
+    /// Noun phrase used in the per-step prompt, e.g. "stale artifact files".
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            RemovalKind::Artifact => "stale artifact files",
+            RemovalKind::OtherToolchain => "artifacts from other toolchains",
+            RemovalKind::Incremental => "stale incremental dirs",
+            RemovalKind::ColdProfile => "cold profile directories",
+            RemovalKind::Fingerprint => "fingerprints of removed units",
+            RemovalKind::RegistryCache => "unreferenced .crate archives",
+            RemovalKind::RegistrySource => "unreferenced registry sources",
+            RemovalKind::GitCheckout => "unreferenced git checkouts",
+        }
+    }
+
+    /// Shorter noun used in the final combined confirmation.
+    pub(crate) fn short_label(self) -> &'static str {
+        match self {
+            RemovalKind::Artifact => "files",
+            RemovalKind::OtherToolchain => "other-toolchain files",
+            RemovalKind::Incremental => "stale incremental dirs",
+            RemovalKind::ColdProfile => "cold profile dirs",
+            RemovalKind::Fingerprint => "fingerprint dirs",
+            RemovalKind::RegistryCache => ".crate archives",
+            RemovalKind::RegistrySource => "registry sources",
+            RemovalKind::GitCheckout => "git checkouts",
+        }
+    }
+
+    /// Heading and "… and N more …" noun for the listing shown before a
+    /// directory step.
+    pub(crate) fn listing(self) -> (&'static str, &'static str) {
+        match self {
+            RemovalKind::Incremental => ("🗂  Stale incremental sessions:", "stale sessions"),
+            RemovalKind::ColdProfile => ("🧊 Cold profile directories:", "directories"),
+            RemovalKind::Fingerprint => ("🔖 Fingerprints of removed units:", "fingerprints"),
+            RemovalKind::RegistrySource => ("📦 Unreferenced registry sources:", "sources"),
+            RemovalKind::GitCheckout => ("🌿 Unreferenced git checkouts:", "checkouts"),
+            RemovalKind::Artifact | RemovalKind::OtherToolchain | RemovalKind::RegistryCache => {
+                ("Files to remove:", "files")
+            }
+        }
+    }
+
+    /// Tag used as the "crate" column of removal errors for directories.
+    pub(crate) fn tag(self) -> &'static str {
+        match self {
+            RemovalKind::Artifact => "artifact",
+            RemovalKind::OtherToolchain => "other-toolchain",
+            RemovalKind::Incremental => "incremental",
+            RemovalKind::ColdProfile => "cold-profile",
+            RemovalKind::Fingerprint => "fingerprint",
+            RemovalKind::RegistryCache => "registry-cache",
+            RemovalKind::RegistrySource => "registry-src",
+            RemovalKind::GitCheckout => "git-checkout",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct FileToRemove {
     pub(crate) path: PathBuf,
+    /// Size in the selected size mode
     pub(crate) size: u64,
+    pub(crate) sizes: DiskSize,
     pub(crate) profile: String,
+    pub(crate) kind: RemovalKind,
+    /// Package, version, features and profile of the unit, when known
+    pub(crate) unit: Option<String>,
 }
 
 #[derive(Clone)]
 pub(crate) struct DirToRemove {
     pub(crate) path: PathBuf,
+    /// Size in the selected size mode
     pub(crate) size: u64,
+    pub(crate) sizes: DiskSize,
     pub(crate) profile: String,
+    pub(crate) kind: RemovalKind,
+}
+
+impl DirToRemove {
+    /// Name used in listings: the path below `incremental/` for incremental
+    /// data (`<unit>/<session>`) or below `build/` for the units of the
+    /// per-unit build-dir layout (`<package>/<hash>`), the path from the
+    /// target or build dir for cold profiles (`target/release`), otherwise the
+    /// directory name.
+    pub(crate) fn display_name(&self) -> String {
+        if self.kind == RemovalKind::ColdProfile
+            && let Some(from_root) = super::roots::target_root(&self.path)
+                .and_then(|root| Some(self.path.strip_prefix(root.parent()?).ok()?.to_path_buf()))
+        {
+            return from_root.to_string_lossy().into_owned();
+        }
+        let below_parent = self
+            .path
+            .ancestors()
+            .find(|a| {
+                a.file_name()
+                    .is_some_and(|n| n == "incremental" || n == "build")
+            })
+            .and_then(|dir| self.path.strip_prefix(dir).ok());
+        below_parent
+            .or_else(|| self.path.file_name().map(Path::new))
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
 }
 
 impl CleanupStats {
     pub(crate) fn merge_from(&mut self, other: CleanupStats) {
         self.files += other.files;
         self.bytes += other.bytes;
+        self.apparent_bytes += other.apparent_bytes;
+        self.disk_bytes += other.disk_bytes;
         self.used_bytes += other.used_bytes;
+        self.retained_builds += other.retained_builds;
+        self.retained_bytes += other.retained_bytes;
+        self.debuginfo_bytes += other.debuginfo_bytes;
         for (name, stat) in other.per_crate {
             let entry = self.per_crate.entry(name).or_default();
             entry.files += stat.files;
             entry.bytes += stat.bytes;
+            entry.units.extend(stat.units);
         }
         for (profile, stat) in other.per_profile {
             let entry = self.per_profile.entry(profile).or_default();
             entry.files += stat.files;
             entry.bytes += stat.bytes;
+            entry.apparent_bytes += stat.apparent_bytes;
+            entry.disk_bytes += stat.disk_bytes;
             entry.used_bytes += stat.used_bytes;
+            entry.retained_bytes += stat.retained_bytes;
+            entry.debuginfo_bytes += stat.debuginfo_bytes;
             entry.total_dir_bytes += stat.total_dir_bytes;
         }
+        for (toolchain, stat) in other.per_toolchain {
+            let entry = self.per_toolchain.entry(toolchain).or_default();
+            entry.files += stat.files;
+            entry.bytes += stat.bytes;
+        }
+        if self.current_toolchain.is_none() {
+            self.current_toolchain = other.current_toolchain;
+        }
+        self.estimated |= other.estimated;
+        self.unlocked_crates.extend(other.unlocked_crates);
+        self.in_use.extend(other.in_use);
+        self.traced_artifacts += other.traced_artifacts;
+        if let Some(d) = other.trace_duration {
+            *self.trace_duration.get_or_insert_default() += d;
+        }
+        self.unclassified.extend(other.unclassified);
         self.errors.extend(other.errors);
         self.files_to_remove.extend(other.files_to_remove);
         self.dirs_to_remove.extend(other.dirs_to_remove);
     }
+
+    /// Every kind that has at least one planned file or directory, in prompt order.
+    pub(crate) fn removal_kinds(&self) -> Vec<RemovalKind> {
+        let mut kinds: Vec<RemovalKind> = self
+            .files_to_remove
+            .iter()
+            .map(|f| f.kind)
+            .chain(self.dirs_to_remove.iter().map(|d| d.kind))
+            .collect();
+        kinds.sort();
+        kinds.dedup();
+        kinds
+    }
+
+    /// Count one file or directory of `sizes` as reclaimable under `profile`.
+    pub(crate) fn add_reclaimable(&mut self, profile: &str, size: u64, sizes: DiskSize) {
+        self.files += 1;
+        self.bytes += size;
+        self.apparent_bytes += sizes.apparent;
+        self.disk_bytes += sizes.allocated;
+        let entry = self.per_profile.entry(profile.to_string()).or_default();
+        entry.files += 1;
+        entry.bytes += size;
+        entry.apparent_bytes += sizes.apparent;
+        entry.disk_bytes += sizes.allocated;
+    }
+
+    /// Count `size` of already reclaimable bytes as split debuginfo.
+    pub(crate) fn add_debuginfo(&mut self, profile: &str, size: u64) {
+        self.debuginfo_bytes += size;
+        self.per_profile
+            .entry(profile.to_string())
+            .or_default()
+            .debuginfo_bytes += size;
+    }
+
+    /// Count `size` as kept by `--keep-last` rather than in use.
+    pub(crate) fn add_retained(&mut self, profile: &str, size: u64) {
+        self.retained_bytes += size;
+        self.per_profile
+            .entry(profile.to_string())
+            .or_default()
+            .retained_bytes += size;
+    }
 }
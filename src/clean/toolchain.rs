@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::ci_cache::unit_hash;
+use super::scan::artifact_stem;
+
+/// How much of an `.rlib` to search for the embedded rustc version.  The
+/// `lib.rmeta` member sits near the start of the archive.
+const RLIB_HEAD_BYTES: u64 = 64 * 1024;
+/// `.rmeta` files carry the version right after the 16-byte header.
+const RMETA_HEAD_BYTES: u64 = 4 * 1024;
+
+/// Which rustc produced each artifact stem in a set of `deps/` directories.
+#[derive(Debug, Default)]
+pub(super) struct ToolchainIndex {
+    /// e.g. `rustc 1.95.0 (59807616e 2026-04-14)`
+    pub(super) current: Option<String>,
+    by_stem: HashMap<String, String>,
+}
+
+impl ToolchainIndex {
+    /// Read the version embedded in every `.rmeta`/`.rlib` and the `rustc`
+    /// hash in every fingerprint, then use stems that have both to resolve the
+    /// version of stems that only have a fingerprint (proc-macros, binaries).
+    pub(super) fn build(target_dir: &Path, deps_dirs: &[PathBuf]) -> Self {
+        let mut by_stem: HashMap<String, String> = HashMap::new();
+        let mut hash_by_stem: HashMap<String, u64> = HashMap::new();
+
+        for deps_dir in deps_dirs {
+            let Ok(entries) = std::fs::read_dir(deps_dir) else {
+                continue;
+            };
+            // Fingerprints are named after the package (`proc-macro2-<hash>`),
+            // artifacts after the crate (`proc_macro2-<hash>`)
+            let fingerprints: HashMap<String, PathBuf> = deps_dir
+                .parent()
+                .and_then(|p| std::fs::read_dir(p.join(".fingerprint")).ok())
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .filter_map(|e| {
+                    let hash = unit_hash(e.file_name().to_str()?)?.to_string();
+                    Some((hash, e.path()))
+                })
+                .collect();
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                let Some(stem) = artifact_stem(&path) else {
+                    continue;
+                };
+                let is_rmeta = path.extension().is_some_and(|e| e == "rmeta");
+                let is_rlib = path.extension().is_some_and(|e| e == "rlib");
+                // Prefer the small .rmeta; only fall back to the .rlib head
+                if (is_rmeta || (is_rlib && !by_stem.contains_key(&stem)))
+                    && let Some(version) = embedded_rustc_version(&path)
+                {
+                    by_stem.insert(stem.clone(), version);
+                }
+                if !hash_by_stem.contains_key(&stem)
+                    && let Some(fingerprint_dir) =
+                        unit_hash(&stem).and_then(|h| fingerprints.get(h))
+                    && let Some(hash) = fingerprint_rustc_hash(fingerprint_dir)
+                {
+                    hash_by_stem.insert(stem, hash);
+                }
+            }
+        }
+
+        let mut version_by_hash: HashMap<u64, String> = HashMap::new();
+        for (stem, hash) in &hash_by_stem {
+            if let Some(version) = by_stem.get(stem) {
+                version_by_hash.insert(*hash, version.clone());
+            }
+        }
+        for (stem, hash) in hash_by_stem {
+            if let Some(version) = version_by_hash.get(&hash) {
+                by_stem.entry(stem).or_insert_with(|| version.clone());
+            }
+        }
+
+        Self {
+            current: current_rustc(target_dir),
+            by_stem,
+        }
+    }
+
+    /// The rustc version that built `stem`, when it could be determined.
+    pub(super) fn version_of(&self, stem: &str) -> Option<&str> {
+        self.by_stem.get(stem).map(String::as_str)
+    }
+
+    /// Whether `stem` was provably built by a rustc other than the current one.
+    /// Unknown versions are never considered stale.
+    pub(super) fn is_other_toolchain(&self, stem: &str) -> bool {
+        match (&self.current, self.version_of(stem)) {
+            (Some(current), Some(version)) => current != version,
+            _ => false,
+        }
+    }
+}
+
+/// The current rustc, from the `rustc -vV` output cargo caches in
+/// `target/.rustc_info.json` (refreshed by every cargo invocation, including
+/// the `cargo metadata` run that precedes cleaning).
+pub(super) fn current_rustc(target_dir: &Path) -> Option<String> {
+    let text = std::fs::read_to_string(target_dir.join(".rustc_info.json")).ok()?;
+    let info: serde_json::Value = serde_json::from_str(&text).ok()?;
+    info.get("outputs")?
+        .as_object()?
+        .values()
+        .filter_map(|o| o.get("stdout")?.as_str())
+        .find(|stdout| stdout.starts_with("rustc ") && stdout.contains("\nrelease: "))
+        .and_then(|stdout| stdout.lines().next())
+        .map(str::to_string)
+}
+
+/// Extract `rustc X.Y.Z (hash date)` from the metadata header of an
+/// `.rmeta`/`.rlib`.
+pub(super) fn embedded_rustc_version(path: &Path) -> Option<String> {
+    let limit = if path.extension().is_some_and(|e| e == "rmeta") {
+        RMETA_HEAD_BYTES
+    } else {
+        RLIB_HEAD_BYTES
+    };
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(limit)
+        .read_to_end(&mut head)
+        .ok()?;
+
+    let needle = b"rustc ";
+    let start = head.windows(needle.len()).enumerate().find_map(|(i, w)| {
+        (w == needle && head.get(i + needle.len()).is_some_and(u8::is_ascii_digit)).then_some(i)
+    })?;
+    let end = head[start..].iter().position(|&b| b == b')')? + start + 1;
+    std::str::from_utf8(&head[start..end])
+        .ok()
+        .map(str::to_string)
+}
+
+/// The `rustc` hash recorded in a unit's fingerprint JSON.
+fn fingerprint_rustc_hash(fingerprint_dir: &Path) -> Option<u64> {
+    std::fs::read_dir(fingerprint_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .find_map(|p| {
+            let text = std::fs::read_to_string(p).ok()?;
+            let json: serde_json::Value = serde_json::from_str(&text).ok()?;
+            json.get("rustc")?.as_u64()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn rmeta_bytes(version: &str) -> Vec<u8> {
+        let mut bytes = b"rust\0\0\0\x0a-\xc2\x0e\0\0\0\0\0".to_vec();
+        bytes.push(version.len() as u8);
+        bytes.extend_from_slice(version.as_bytes());
+        bytes.extend_from_slice(b"\xc1\x02\xba");
+        bytes
+    }
+
+    fn fingerprint(profile: &Path, stem: &str, rustc: u64) {
+        let dir = profile.join(".fingerprint").join(stem);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("lib-x.json"),
+            format!(r#"{{"rustc":{rustc},"features":"[]"}}"#),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn reads_version_from_rmeta_header() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("libfoo-abc.rmeta");
+        fs::write(&path, rmeta_bytes("rustc 1.95.0 (59807616e 2026-04-14)")).unwrap();
+        assert_eq!(
+            embedded_rustc_version(&path).as_deref(),
+            Some("rustc 1.95.0 (59807616e 2026-04-14)")
+        );
+    }
+
+    #[test]
+    fn groups_stems_and_resolves_through_fingerprints() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path();
+        let profile = target.join("debug");
+        let deps = profile.join("deps");
+        fs::create_dir_all(&deps).unwrap();
+        fs::write(
+            target.join(".rustc_info.json"),
+            r#"{"outputs":{"1":{"stdout":"rustc 1.95.0 (new 2026-04-14)\nbinary: rustc\nrelease: 1.95.0\n"}}}"#,
+        )
+        .unwrap();
+
+        fs::write(
+            deps.join("libnew-1.rmeta"),
+            rmeta_bytes("rustc 1.95.0 (new 2026-04-14)"),
+        )
+        .unwrap();
+        fs::write(
+            deps.join("libold-2.rmeta"),
+            rmeta_bytes("rustc 1.94.0 (old 2026-03-01)"),
+        )
+        .unwrap();
+        // A proc-macro from the old compiler: no rmeta, only a fingerprint
+        fs::write(deps.join("libmacro-3.so"), b"\x7fELF").unwrap();
+        fs::write(deps.join("libmystery-4.so"), b"\x7fELF").unwrap();
+        // A dashed package: its fingerprint doesn't share the artifact's stem
+        fs::write(deps.join("libproc_macro2-5.rlib"), b"!<arch>\n").unwrap();
+        fingerprint(&profile, "new-1", 111);
+        fingerprint(&profile, "old-2", 222);
+        fingerprint(&profile, "macro-3", 222);
+        fingerprint(&profile, "proc-macro2-5", 222);
+
+        let index = ToolchainIndex::build(target, &[deps]);
+
+        assert_eq!(
+            index.current.as_deref(),
+            Some("rustc 1.95.0 (new 2026-04-14)")
+        );
+        assert!(!index.is_other_toolchain("new-1"));
+        assert!(index.is_other_toolchain("old-2"));
+        assert!(index.is_other_toolchain("macro-3"));
+        assert!(index.is_other_toolchain("proc_macro2-5"));
+        // Unknown provenance is never flagged
+        assert!(!index.is_other_toolchain("mystery-4"));
+    }
+}
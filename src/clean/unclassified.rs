@@ -0,0 +1,132 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::scan::SizeCache;
+use super::stats::{CleanupStats, UnclassifiedAction, UnclassifiedFile};
+
+/// A rule that keeps an unclassified file, returning why.
+type KeepRule = fn(&UnclassifiedRules, &Path) -> Option<String>;
+
+/// Checked in order; a file no rule keeps is only reported, never removed.
+const KEEP_RULES: &[KeepRule] = &[hard_link_of_traced, build_output];
+
+/// Decides what happens to files in `deps/` without a `crate-HASH` stem
+/// (e.g. `libserde.rlib` from custom build systems or `--out-dir` copies),
+/// which can't be matched against the trace.
+pub(super) struct UnclassifiedRules<'a> {
+    sizes: &'a SizeCache,
+    /// `(dev, ino)` of traced artifacts that have other hard links
+    traced_inodes: HashMap<(u64, u64), &'a Path>,
+    /// Crate names of the final outputs in the profile directory
+    output_names: &'a HashSet<String>,
+}
+
+/// Keep hard-linked copies of a traced artifact: removing them frees nothing.
+fn hard_link_of_traced(rules: &UnclassifiedRules, path: &Path) -> Option<String> {
+    let inode = rules.sizes.inode_of(path)?;
+    let traced = rules.traced_inodes.get(&(inode.dev, inode.ino))?;
+    let name = traced.file_name()?.to_string_lossy();
+    Some(format!("hard link of traced {name}"))
+}
+
+/// Keep files named after a final output of the profile (e.g. `libfoo.rlib`
+/// next to an uplifted `libfoo.rlib`).
+fn build_output(rules: &UnclassifiedRules, path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let name = stem.strip_prefix("lib").unwrap_or(stem).replace('-', "_");
+    rules
+        .output_names
+        .contains(&name)
+        .then(|| "named after a build output".to_string())
+}
+
+impl<'a> UnclassifiedRules<'a> {
+    pub(super) fn new(
+        used_artifacts: impl IntoIterator<Item = &'a PathBuf>,
+        output_names: &'a HashSet<String>,
+        sizes: &'a SizeCache,
+    ) -> Self {
+        let traced_inodes = used_artifacts
+            .into_iter()
+            .filter_map(|p| {
+                let inode = sizes.inode_of(p)?;
+                Some(((inode.dev, inode.ino), p.as_path()))
+            })
+            .collect();
+        Self {
+            sizes,
+            traced_inodes,
+            output_names,
+        }
+    }
+
+    pub(super) fn classify(&self, path: &Path) -> UnclassifiedAction {
+        KEEP_RULES
+            .iter()
+            .find_map(|rule| rule(self, path))
+            .map_or(UnclassifiedAction::Report, UnclassifiedAction::Keep)
+    }
+}
+
+impl CleanupStats {
+    /// Classify `path` with `rules` and record it; kept files count as in use.
+    pub(super) fn record_unclassified(
+        &mut self,
+        rules: &UnclassifiedRules,
+        path: PathBuf,
+        profile: &str,
+        size: u64,
+    ) {
+        let action = rules.classify(&path);
+        if matches!(action, UnclassifiedAction::Keep(_)) {
+            self.used_bytes += size;
+            self.per_profile
+                .entry(profile.to_string())
+                .or_default()
+                .used_bytes += size;
+        }
+        self.unclassified.push(UnclassifiedFile {
+            path,
+            profile: profile.to_string(),
+            size,
+            action,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hard_links_and_outputs_are_kept_the_rest_reported() {
+        let tmp = tempfile::tempdir().unwrap();
+        let deps = tmp.path().join("deps");
+        fs::create_dir(&deps).unwrap();
+        let traced = deps.join("libserde-abc.rlib");
+        fs::write(&traced, b"rlib").unwrap();
+        fs::hard_link(&traced, deps.join("libserde.rlib")).unwrap();
+        fs::write(deps.join("libapp.rlib"), b"rlib").unwrap();
+        fs::write(deps.join("libstray.rlib"), b"rlib").unwrap();
+
+        let sizes = SizeCache::scan([tmp.path().to_path_buf()]).await.unwrap();
+        let outputs = HashSet::from(["app".to_string()]);
+        let used = [traced];
+        let rules = UnclassifiedRules::new(&used, &outputs, &sizes);
+
+        assert_eq!(
+            rules.classify(&deps.join("libserde.rlib")),
+            UnclassifiedAction::Keep("hard link of traced libserde-abc.rlib".to_string())
+        );
+        assert!(matches!(
+            rules.classify(&deps.join("libapp.rlib")),
+            UnclassifiedAction::Keep(_)
+        ));
+        assert_eq!(
+            rules.classify(&deps.join("libstray.rlib")),
+            UnclassifiedAction::Report
+        );
+    }
+}
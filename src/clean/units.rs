@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use cargo_metadata::Metadata;
+use serde::Deserialize;
+
+use crate::crate_deps::crate_key;
+
+use super::dep_graph::DepGraph;
+use super::scan::artifact_stem;
+use super::stats::{CleanupStats, RemovalKind};
+
+/// Target kinds as cargo names fingerprint files (`<flavor><kind>-<target>`).
+const TARGET_KINDS: &[&str] = &[
+    "lib",
+    "bin",
+    "integration-test",
+    "example",
+    "bench",
+    "build-script",
+];
+
+/// The parts of `.fingerprint/<unit>/<kind>-<target>.json` we use.
+#[derive(Debug, Deserialize)]
+struct Fingerprint {
+    /// Enabled features, itself JSON: `["default", "std"]`
+    features: String,
+}
+
+/// A package of the resolve and where its sources live.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PackageDir {
+    pub(super) dir: PathBuf,
+    pub(super) name: String,
+    pub(super) version: String,
+    /// `None` for path packages
+    pub(super) source: Option<String>,
+}
+
+/// Source directories of every package in `metadata`.
+pub(super) fn package_dirs(metadata: &Metadata) -> Vec<PackageDir> {
+    metadata
+        .packages
+        .iter()
+        .filter_map(|p| {
+            Some(PackageDir {
+                dir: p.manifest_path.parent()?.as_std_path().to_path_buf(),
+                name: p.name.clone(),
+                version: p.version.to_string(),
+                source: p.source.as_ref().map(|s| s.repr.clone()),
+            })
+        })
+        .collect()
+}
+
+/// What one compilation unit in `deps/` is.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct UnitInfo {
+    /// Package name (or the fingerprint's name when no package matched)
+    pub(super) name: String,
+    pub(super) version: Option<String>,
+    /// `registry`, `git` or `path`, when the package is known
+    pub(super) source: Option<&'static str>,
+    pub(super) features: Vec<String>,
+    /// `lib`, `bin`, `test`, … from the fingerprint file name
+    pub(super) kind: String,
+    pub(super) profile: String,
+}
+
+impl UnitInfo {
+    /// `serde 1.0.190`
+    pub(super) fn package(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{} {version}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// `serde 1.0.190 [derive,std] release`, with the kind unless it is a
+    /// plain library and the source unless it is a registry.
+    pub(super) fn label(&self) -> String {
+        let mut label = self.package();
+        if let Some(source) = self.source.filter(|s| *s != "registry") {
+            label.push_str(&format!(" ({source})"));
+        }
+        if !self.features.is_empty() {
+            label.push_str(&format!(" [{}]", self.features.join(",")));
+        }
+        if self.kind != "lib" {
+            label.push_str(&format!(" {}", self.kind));
+        }
+        label.push_str(&format!(" {}", self.profile));
+        label
+    }
+}
+
+/// `test`, `bin`, `build-script`, … from a fingerprint file name such as
+/// `test-lib-foo.json`; test units are reported as `test` whatever they test.
+pub(super) fn unit_kind(file_name: &str) -> Option<String> {
+    unit_target(file_name).map(|(kind, _)| kind)
+}
+
+/// The kind of `unit_kind` and the name of the target, which dep-info files
+/// are named after: `test-integration-test-clean` is the `clean` test.
+fn unit_target(file_name: &str) -> Option<(String, &str)> {
+    let (flavor, rest) = ["test-", "doc-", "run-"]
+        .iter()
+        .find_map(|f| {
+            file_name
+                .strip_prefix(f)
+                .map(|rest| (&f[..f.len() - 1], rest))
+        })
+        .unwrap_or(("", file_name));
+    let (kind, target) = TARGET_KINDS.iter().find_map(|k| {
+        let target = rest.strip_prefix(*k)?.strip_prefix('-')?;
+        Some((k, target))
+    })?;
+    let kind = match flavor {
+        "" => kind.to_string(),
+        "test" => "test".to_string(),
+        flavor => format!("{flavor}-{kind}"),
+    };
+    Some((kind, target))
+}
+
+fn source_kind(source: &str) -> &'static str {
+    if source.starts_with("git+") {
+        "git"
+    } else {
+        "registry"
+    }
+}
+
+/// The package whose directory contains the most of `sources`.
+fn owning_package<'a>(
+    sources: impl Iterator<Item = &'a Path>,
+    packages: &'a [PackageDir],
+) -> Option<&'a PackageDir> {
+    let mut hits: HashMap<usize, usize> = HashMap::new();
+    for source in sources {
+        // The innermost package dir wins (a workspace member below the root)
+        let owner = packages
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| source.starts_with(&p.dir))
+            .max_by_key(|(_, p)| p.dir.components().count());
+        if let Some((index, _)) = owner {
+            *hits.entry(index).or_default() += 1;
+        }
+    }
+    hits.into_iter()
+        .max_by_key(|&(index, count)| (count, std::cmp::Reverse(index)))
+        .map(|(index, _)| &packages[index])
+}
+
+/// Units of one profile directory, by unit hash.
+#[derive(Debug, Default)]
+pub(super) struct UnitIndex {
+    units: HashMap<String, UnitInfo>,
+}
+
+impl UnitIndex {
+    /// Read the fingerprints of `profile_dir` and attribute each unit to a
+    /// package through the sources its dep-info lists.
+    pub(super) fn load(
+        profile_dir: &Path,
+        profile: &str,
+        graph: &DepGraph,
+        packages: &[PackageDir],
+    ) -> Self {
+        let mut index = UnitIndex::default();
+        let fingerprints = std::fs::read_dir(profile_dir.join(".fingerprint"));
+        for unit_dir in fingerprints
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+        {
+            let Some((fallback_name, hash)) = unit_dir
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.rsplit_once('-'))
+            else {
+                continue;
+            };
+            let Some((kind, target, fingerprint)) = read_fingerprint(&unit_dir) else {
+                continue;
+            };
+            let stem = format!("{}-{hash}", target.replace('-', "_"));
+            let package = owning_package(graph.sources_of(&stem), packages);
+            let features = serde_json::from_str(&fingerprint.features).unwrap_or_default();
+            index.units.insert(
+                hash.to_string(),
+                UnitInfo {
+                    name: package.map_or_else(|| fallback_name.to_string(), |p| p.name.clone()),
+                    version: package.map(|p| p.version.clone()),
+                    source: package.map(|p| p.source.as_deref().map_or("path", source_kind)),
+                    features,
+                    kind,
+                    profile: profile.to_string(),
+                },
+            );
+        }
+        index
+    }
+
+    pub(super) fn extend(&mut self, other: UnitIndex) {
+        self.units.extend(other.units);
+    }
+
+    /// The unit an artifact in `deps/` belongs to.
+    pub(super) fn get(&self, artifact: &Path) -> Option<&UnitInfo> {
+        let stem = artifact_stem(artifact)?;
+        let (_, hash) = stem.rsplit_once('-')?;
+        self.units.get(hash)
+    }
+
+    /// Label every planned artifact removal with its unit, and record the
+    /// units behind each crate of `per_crate`.
+    pub(super) fn annotate(&self, stats: &mut CleanupStats) {
+        for file in stats
+            .files_to_remove
+            .iter_mut()
+            .filter(|f| f.kind == RemovalKind::Artifact)
+        {
+            if let Some(unit) = self.get(&file.path) {
+                file.unit = Some(unit.label());
+                if let Some(stat) = stats.per_crate.get_mut(&crate_key(&file.path)) {
+                    stat.units.insert(unit.label());
+                }
+            }
+        }
+    }
+}
+
+/// The kind, target name and contents of the JSON fingerprint in a
+/// `.fingerprint/` unit dir.
+fn read_fingerprint(unit_dir: &Path) -> Option<(String, String, Fingerprint)> {
+    std::fs::read_dir(unit_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "json"))
+        .find_map(|path| {
+            let (kind, target) = unit_target(path.file_stem()?.to_str()?)?;
+            let text = std::fs::read_to_string(&path).ok()?;
+            Some((kind, target.to_string(), serde_json::from_str(&text).ok()?))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn kinds_come_from_fingerprint_file_names() {
+        assert_eq!(unit_kind("lib-serde").as_deref(), Some("lib"));
+        assert_eq!(unit_kind("bin-my-app").as_deref(), Some("bin"));
+        assert_eq!(unit_kind("test-lib-serde").as_deref(), Some("test"));
+        assert_eq!(
+            unit_kind("test-integration-test-clean").as_deref(),
+            Some("test")
+        );
+        assert_eq!(
+            unit_kind("build-script-build-script-build").as_deref(),
+            Some("build-script")
+        );
+        assert_eq!(
+            unit_kind("run-build-script-build-script-build").as_deref(),
+            Some("run-build-script")
+        );
+        assert_eq!(unit_kind("dep-lib-serde"), None);
+        assert_eq!(
+            unit_target("test-integration-test-clean"),
+            Some(("test".to_string(), "clean"))
+        );
+        assert_eq!(unit_target("lib-md5"), Some(("lib".to_string(), "md5")));
+    }
+
+    #[test]
+    fn units_resolve_to_packages_through_their_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile = tmp.path().join("release");
+        let deps = profile.join("deps");
+        let registry = tmp.path().join("registry/serde-1.0.190");
+        fs::create_dir_all(&deps).unwrap();
+        fs::create_dir_all(registry.join("src")).unwrap();
+        for (unit, file, features) in [
+            ("serde-9f3a", "lib-serde.json", r#"[\"derive\", \"std\"]"#),
+            ("my-app-77aa", "bin-my-app.json", "[]"),
+            // An integration test of serde, named after its file
+            ("serde-5b1c", "test-integration-test-ser.json", "[]"),
+        ] {
+            let dir = profile.join(".fingerprint").join(unit);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join(file),
+                format!(r#"{{"rustc":1,"features":"{features}","deps":[]}}"#),
+            )
+            .unwrap();
+        }
+        fs::write(
+            deps.join("serde-9f3a.d"),
+            format!(
+                "{}: {}\n",
+                deps.join("libserde-9f3a.rlib").display(),
+                registry.join("src/lib.rs").display()
+            ),
+        )
+        .unwrap();
+        fs::write(
+            deps.join("ser-5b1c.d"),
+            format!(
+                "{}: {}\n",
+                deps.join("ser-5b1c").display(),
+                registry.join("tests/ser.rs").display()
+            ),
+        )
+        .unwrap();
+        let packages = [PackageDir {
+            dir: registry,
+            name: "serde".to_string(),
+            version: "1.0.190".to_string(),
+            source: Some("registry+https://github.com/rust-lang/crates.io-index".to_string()),
+        }];
+
+        let graph = DepGraph::load([deps.as_path()], tmp.path());
+        let index = UnitIndex::load(&profile, "release", &graph, &packages);
+        assert_eq!(
+            index.get(&deps.join("libserde-9f3a.rlib")).unwrap().label(),
+            "serde 1.0.190 [derive,std] release"
+        );
+        assert_eq!(
+            index.get(&deps.join("ser-5b1c")).unwrap().label(),
+            "serde 1.0.190 test release"
+        );
+        // No dep-info: named after the fingerprint, without a version
+        assert_eq!(
+            index.get(&deps.join("my_app-77aa")).unwrap().label(),
+            "my-app bin release"
+        );
+        assert!(index.get(&deps.join("libother-1234.rlib")).is_none());
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::{ArgAction, Args, ValueHint};
+
+use crate::crate_deps::{format_bytes, format_utc, parse_interval, parse_size};
+
+use super::CleanCommand;
+use super::manifest::{self, TraceManifest};
+use super::prompt::RemovalSelection;
+use super::scan::{SizeCache, SizeMode, discover_deps_dirs, output_crate_names, traced_deps_dirs};
+
+/// Default log file, relative to the target directory.
+const LOG_FILE: &str = ".clean-artifact/watch.log";
+
+/// Keep target directories clean in the background.
+///
+/// Polls each target directory and cleans it with the stored trace manifests
+/// (recorded by every `-c` run) when a profile grows past a size, or on a
+/// fixed interval. A profile is skipped while cargo holds its build lock.
+#[derive(Debug, Clone, Args)]
+pub(crate) struct WatchCommand {
+    /// Target directories to watch.
+    #[clap(
+        value_hint = ValueHint::DirPath,
+        default_value = "target",
+        value_name = "TARGET_DIR"
+    )]
+    target_dirs: Vec<PathBuf>,
+
+    /// Clean when a profile directory grows past SIZE (e.g. 10GiB).
+    #[clap(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        required_unless_present = "every"
+    )]
+    max_profile_size: Option<u64>,
+
+    /// Clean on a fixed interval (e.g. 6h, 1d), whatever the size.
+    #[clap(long, value_name = "INTERVAL", value_parser = parse_interval)]
+    every: Option<Duration>,
+
+    /// How often to check the target directories.
+    #[clap(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_poll,
+        default_value = "60s"
+    )]
+    poll: Duration,
+
+    /// Append the log to FILE instead of <TARGET_DIR>/.clean-artifact/watch.log.
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    log: Option<PathBuf>,
+
+    /// Log what would be removed without removing anything.
+    #[clap(long, action = ArgAction::SetTrue)]
+    dry_run: bool,
+
+    /// Check once and exit (e.g. when run from cron).
+    #[clap(long, action = ArgAction::SetTrue)]
+    once: bool,
+}
+
+/// What `watch` remembers about one target directory between polls.
+#[derive(Debug, Default)]
+struct WatchState {
+    last_clean: Option<Instant>,
+    /// Profile dir sizes right after the last clean
+    settled: HashMap<PathBuf, u64>,
+}
+
+/// A poll interval: like `--every`, but never zero, which would re-measure
+/// every target dir in a busy loop.
+fn parse_poll(text: &str) -> Result<Duration, String> {
+    let interval = parse_interval(text)?;
+    if interval.is_zero() {
+        return Err(format!("poll interval `{text}` must be at least 1s"));
+    }
+    Ok(interval)
+}
+
+/// Outcome of trying to take cargo's build lock on a profile directory.
+enum BuildLock {
+    /// Released when dropped
+    Held(File),
+    /// No lock file: cargo never built here (or it was removed)
+    Absent,
+    Busy,
+}
+
+/// Take the lock cargo holds on `<profile>/.cargo-lock` for the duration of
+/// a build, without waiting.
+fn try_build_lock(profile_dir: &Path) -> Result<BuildLock> {
+    let path = profile_dir.join(".cargo-lock");
+    let file = match OpenOptions::new().read(true).write(true).open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BuildLock::Absent),
+        Err(e) => return Err(e).with_context(|| format!("opening {}", path.display())),
+    };
+    match file.try_lock() {
+        Ok(()) => Ok(BuildLock::Held(file)),
+        Err(TryLockError::WouldBlock) => Ok(BuildLock::Busy),
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("locking {}", path.display()))
+        }
+    }
+}
+
+impl WatchCommand {
+    fn log_line(&self, target_dir: &Path, message: &str) {
+        let line = format!(
+            "{} {} {message}",
+            format_utc(SystemTime::now()),
+            target_dir.display()
+        );
+        println!("{line}");
+        let path = self
+            .log
+            .clone()
+            .unwrap_or_else(|| target_dir.join(LOG_FILE));
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| OpenOptions::new().create(true).append(true).open(&path))
+            .and_then(|mut f| writeln!(f, "{line}"));
+        if let Err(e) = written {
+            log::warn!("Failed to write watch log {}: {e}", path.display());
+        }
+    }
+
+    /// Current size of every profile directory of `target_dir`.
+    async fn profile_sizes(target_dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let profile_dirs: Vec<PathBuf> = discover_deps_dirs(target_dir)
+            .into_iter()
+            .filter_map(|(deps, _)| deps.parent().map(Path::to_path_buf))
+            .collect();
+        let sizes = SizeCache::scan(profile_dirs.clone()).await?;
+        Ok(profile_dirs
+            .into_iter()
+            .map(|dir| {
+                let size = sizes.size_of(&dir).get(SizeMode::DiskUsage);
+                (dir, size)
+            })
+            .collect())
+    }
+
+    /// Why `target_dir` should be cleaned now, if it should.
+    async fn trigger(&self, target_dir: &Path, state: &WatchState) -> Result<Option<String>> {
+        if let Some(every) = self.every
+            && state.last_clean.is_none_or(|t| t.elapsed() >= every)
+        {
+            return Ok(Some("scheduled".to_string()));
+        }
+        let Some(limit) = self.max_profile_size else {
+            return Ok(None);
+        };
+        // A profile still over the limit after a clean only triggers again
+        // once it grows past the size it was left at.
+        Ok(Self::profile_sizes(target_dir)
+            .await?
+            .into_iter()
+            .find(|(dir, size)| {
+                *size > limit && *size > state.settled.get(dir).copied().unwrap_or(0)
+            })
+            .map(|(dir, size)| {
+                format!(
+                    "{} is {} (> {})",
+                    dir.display(),
+                    format_bytes(size),
+                    format_bytes(limit)
+                )
+            }))
+    }
+
+    /// Clean every traced profile of `target_dir` with its stored manifests.
+    /// Returns `false` when a profile had to be skipped because of a build.
+    async fn clean(&self, target_dir: &Path, manifests: &[TraceManifest]) -> Result<bool> {
+        let mut used: HashSet<PathBuf> = manifests
+            .iter()
+            .flat_map(|m| m.artifacts.iter().cloned())
+            .collect();
+        // Anything built after the oldest trace may belong to a build no
+        // manifest has seen yet: never remove it.
+        let oldest = manifests
+            .iter()
+            .map(TraceManifest::recorded_at)
+            .min()
+            .unwrap_or(UNIX_EPOCH);
+
+        let deps_dirs = traced_deps_dirs(&used, target_dir);
+        let sizes = SizeCache::scan(
+            deps_dirs
+                .iter()
+                .filter_map(|(d, _)| d.parent())
+                .filter(|p| p.exists())
+                .map(Path::to_path_buf),
+        )
+        .await?;
+
+        let mut complete = true;
+        for (deps_dir, profile) in &deps_dirs {
+            let Some(profile_dir) = deps_dir.parent() else {
+                continue;
+            };
+            if !deps_dir.exists() {
+                continue;
+            }
+            let _lock = match try_build_lock(profile_dir)? {
+                BuildLock::Busy => {
+                    self.log_line(
+                        target_dir,
+                        &format!("{profile}: build in progress, skipped"),
+                    );
+                    complete = false;
+                    continue;
+                }
+                // Held until this profile is cleaned, so no build starts meanwhile
+                BuildLock::Held(file) => Some(file),
+                BuildLock::Absent => None,
+            };
+
+            for entry in std::fs::read_dir(deps_dir)?.filter_map(|e| e.ok()) {
+                let newer = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|mtime| mtime > oldest);
+                if newer {
+                    used.insert(entry.path());
+                }
+            }
+
+            let outputs = output_crate_names(profile_dir);
+            let mut stats = CleanCommand::clean_with_trace_result(
+                deps_dir,
+                &used,
+                &outputs,
+                profile,
+                &sizes,
+                SizeMode::DiskUsage,
+                0,
+            )
+            .await?;
+            stats.merge_from(
+                CleanCommand::clean_incremental_dir(
+                    profile_dir,
+                    &used,
+                    &outputs,
+                    profile,
+                    &sizes,
+                    SizeMode::DiskUsage,
+                )
+                .await?,
+            );
+
+            if stats.files == 0 {
+                self.log_line(target_dir, &format!("{profile}: nothing to remove"));
+            } else if self.dry_run {
+                self.log_line(
+                    target_dir,
+                    &format!(
+                        "{profile}: would remove {} items ({})",
+                        stats.files,
+                        format_bytes(stats.bytes)
+                    ),
+                );
+            } else {
+                let removed = CleanCommand::actually_remove_files(
+                    &stats,
+                    &RemovalSelection::everything(&stats),
+                )
+                .await?;
+                self.log_line(
+                    target_dir,
+                    &format!(
+                        "{profile}: removed {} items ({}), {} errors",
+                        removed.files,
+                        format_bytes(removed.bytes),
+                        removed.errors.len()
+                    ),
+                );
+            }
+        }
+        Ok(complete)
+    }
+
+    async fn check(&self, target_dir: &Path, state: &mut WatchState) -> Result<()> {
+        if !target_dir.is_dir() {
+            log::debug!("{} does not exist yet", target_dir.display());
+            return Ok(());
+        }
+        let Some(reason) = self.trigger(target_dir, state).await? else {
+            return Ok(());
+        };
+
+        let manifests = manifest::load_all(target_dir);
+        if manifests.is_empty() {
+            self.log_line(
+                target_dir,
+                &format!(
+                    "{reason}: no stored trace; run `cargo clean-artifact -c <build command>` once"
+                ),
+            );
+        } else {
+            self.log_line(
+                target_dir,
+                &format!(
+                    "{reason}: cleaning with {} stored trace(s)",
+                    manifests.len()
+                ),
+            );
+            if !self.clean(target_dir, &manifests).await? {
+                // Retry on the next poll, once the build has finished
+                return Ok(());
+            }
+        }
+        state.last_clean = Some(Instant::now());
+        state.settled = Self::profile_sizes(target_dir).await?.into_iter().collect();
+        Ok(())
+    }
+
+    pub(crate) async fn run(self) -> Result<()> {
+        // Manifests record absolute paths
+        let target_dirs: Vec<PathBuf> = self
+            .target_dirs
+            .iter()
+            .map(|d| std::path::absolute(d).unwrap_or_else(|_| d.clone()))
+            .collect();
+        let mut states: HashMap<PathBuf, WatchState> = HashMap::new();
+        loop {
+            for target_dir in &target_dirs {
+                let state = states.entry(target_dir.clone()).or_default();
+                if let Err(e) = self.check(target_dir, state).await {
+                    self.log_line(target_dir, &format!("error: {e:#}"));
+                }
+            }
+            if self.once {
+                return Ok(());
+            }
+            tokio::time::sleep(self.poll).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polling_needs_a_pause() {
+        assert_eq!(parse_poll("90s"), Ok(Duration::from_secs(90)));
+        assert!(parse_poll("0s").is_err());
+        assert!(parse_poll("0").is_err());
+    }
+
+    #[test]
+    fn build_lock_is_busy_while_cargo_holds_it() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            try_build_lock(tmp.path()).unwrap(),
+            BuildLock::Absent
+        ));
+
+        let cargo = File::create(tmp.path().join(".cargo-lock")).unwrap();
+        cargo.lock().unwrap();
+        assert!(matches!(
+            try_build_lock(tmp.path()).unwrap(),
+            BuildLock::Busy
+        ));
+        cargo.unlock().unwrap();
+        assert!(matches!(
+            try_build_lock(tmp.path()).unwrap(),
+            BuildLock::Held(_)
+        ));
+    }
+}
@@ -1,6 +1,9 @@
-use crate::clean::CleanCommand;
+use crate::clean::{
+    CiCacheCommand, CleanCommand, HomeCommand, InstallHookCommand, StatsCommand,
+    UninstallHookCommand, WatchCommand,
+};
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -9,10 +12,33 @@ use clap::Parser;
     about = "Clean old build artifacts / deps that are not used in any features of a workspace."
 )]
 pub struct CliArgs {
+    #[command(subcommand)]
+    subcommand: Option<CliSubcommand>,
+
     #[command(flatten)]
     cmd: CleanCommand,
 }
 
+#[derive(Debug, Subcommand)]
+enum CliSubcommand {
+    /// Clean the cargo home: registry sources, `.crate` archives and git
+    /// checkouts not referenced by any known workspace's Cargo.lock.
+    Home(HomeCommand),
+    /// Prune `target/` before a CI cache is saved: drop every artifact the
+    /// job's traced commands did not use, plus `incremental/`. Never prompts.
+    CiCache(CiCacheCommand),
+    /// Keep target directories clean in the background, using the traces
+    /// recorded by earlier `-c` runs.
+    Watch(WatchCommand),
+    /// Show what past cleans reclaimed and which crates keep going stale.
+    Stats(StatsCommand),
+    /// Install git hooks that report on (or clean) `target/` in the
+    /// background whenever a checkout or merge changes `Cargo.lock`.
+    InstallHook(InstallHookCommand),
+    /// Remove the git hooks written by `install-hook`.
+    UninstallHook(UninstallHookCommand),
+}
+
 impl CliArgs {
     /// Parse arguments, handling cargo subcommand invocation
     pub fn parse_args() -> Self {
@@ -29,7 +55,15 @@ impl CliArgs {
     }
 
     pub async fn run(self) -> Result<()> {
-        self.cmd.run().await
+        match self.subcommand {
+            Some(CliSubcommand::Home(home)) => home.run().await,
+            Some(CliSubcommand::CiCache(ci_cache)) => ci_cache.run().await,
+            Some(CliSubcommand::Watch(watch)) => watch.run().await,
+            Some(CliSubcommand::Stats(stats)) => stats.run().await,
+            Some(CliSubcommand::InstallHook(install)) => install.run().await,
+            Some(CliSubcommand::UninstallHook(uninstall)) => uninstall.run().await,
+            None => self.cmd.run().await,
+        }
     }
 
     pub fn is_verbose(&self) -> bool {
@@ -1,5 +1,6 @@
 use anstyle::{Reset, Style};
 use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub(crate) fn paint(enabled: bool, text: impl AsRef<str>, style: Style) -> String {
     if !enabled {
@@ -38,6 +39,79 @@ pub(crate) fn format_bytes(bytes: u64) -> String {
     }
 }
 
+/// Parse a human-readable size such as `1GiB`, `500M`, `1.5 GB` or `1024`.
+///
+/// Single-letter and `*iB` suffixes are binary (like `format_bytes`); `KB`,
+/// `MB`, … are decimal.
+pub(crate) fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size `{text}`: expected e.g. 1GiB, 500M or 1024"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        other => return Err(format!("invalid size unit `{other}` in `{text}`")),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parse an interval such as `90s`, `15m`, `6h` or `1d` (bare numbers are seconds).
+pub(crate) fn parse_interval(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval `{text}`: expected e.g. 90s, 15m, 6h or 1d"))?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        other => return Err(format!("invalid interval unit `{other}` in `{text}`")),
+    };
+    let seconds = number
+        .checked_mul(seconds)
+        .ok_or_else(|| format!("interval `{text}` is too large"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ`, for logs and the run ledger.
+pub(crate) fn format_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,4 +149,36 @@ mod tests {
         assert_eq!(format_bytes(1024 * 1024 * 1024), "1.00 GiB");
         assert_eq!(format_bytes(1536), "1.50 KiB");
     }
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size("1.5 gb"), Ok(1_500_000_000));
+        assert!(parse_size("GiB").is_err());
+        assert!(parse_size("3 parsecs").is_err());
+    }
+
+    #[test]
+    fn format_utc_dates() {
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_utc(UNIX_EPOCH + std::time::Duration::from_secs(1_709_251_199)),
+            "2024-02-29T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_interval("1d"), Ok(Duration::from_secs(86_400)));
+        assert!(parse_interval("soon").is_err());
+        assert!(parse_interval("5w").is_err());
+        assert_eq!(
+            parse_interval("999999999999999999d"),
+            Err("interval `999999999999999999d` is too large".to_string())
+        );
+    }
 }
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Directories never descended into while looking for workspaces.
+const SKIP_DIRS: &[&str] = &["target", "node_modules"];
+
+/// One `[[package]]` entry of a `Cargo.lock`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub(crate) struct LockedPackage {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// e.g. `registry+https://github.com/rust-lang/crates.io-index` or
+    /// `git+https://github.com/foo/bar?branch=main#<commit>`; absent for path deps.
+    pub(crate) source: Option<String>,
+}
+
+/// Whether a package source is a crates registry (git index or sparse).
+pub(crate) fn is_registry_source(source: &str) -> bool {
+    source.starts_with("registry+") || source.starts_with("sparse+")
+}
+
+/// The locked commit of a git package source (the part after `#`).
+pub(crate) fn git_commit(source: &str) -> Option<&str> {
+    source
+        .strip_prefix("git+")
+        .and_then(|s| s.rsplit_once('#'))
+        .map(|(_, commit)| commit)
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+/// Parse the packages recorded in a `Cargo.lock`.
+pub(crate) fn parse_lockfile(path: &Path) -> Result<Vec<LockedPackage>> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let lock: Lockfile =
+        toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+    Ok(lock.package)
+}
+
+/// Recursively find every `Cargo.lock` below `root` (including `root` itself).
+///
+/// Hidden directories and build output directories are skipped, so this stays
+/// cheap even on a home directory full of projects.
+pub(crate) fn find_lockfiles(root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let lockfile = dir.join("Cargo.lock");
+        if lockfile.is_file() {
+            found.push(lockfile);
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if is_dir && !name.starts_with('.') && !SKIP_DIRS.contains(&name.as_ref()) {
+                stack.push(entry.path());
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = r#"
+version = 4
+
+[[package]]
+name = "my_app"
+version = "0.1.0"
+
+[[package]]
+name = "serde"
+version = "1.0.228"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "abc"
+
+[[package]]
+name = "forked"
+version = "0.2.0"
+source = "git+https://github.com/me/forked?branch=main#0123456789abcdef0123456789abcdef01234567"
+"#;
+
+    #[test]
+    fn parses_packages_and_sources() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("Cargo.lock");
+        std::fs::write(&path, LOCK).unwrap();
+
+        let pkgs = parse_lockfile(&path).unwrap();
+        assert_eq!(pkgs.len(), 3);
+        assert_eq!(pkgs[0].source, None);
+        assert!(is_registry_source(pkgs[1].source.as_deref().unwrap()));
+        assert_eq!(git_commit(pkgs[1].source.as_deref().unwrap()), None);
+        assert_eq!(
+            git_commit(pkgs[2].source.as_deref().unwrap()),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+    }
+
+    #[test]
+    fn find_lockfiles_skips_target_and_hidden_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        for dir in ["a", "b/nested", "target/package/x", ".hidden"] {
+            std::fs::create_dir_all(tmp.path().join(dir)).unwrap();
+            std::fs::write(tmp.path().join(dir).join("Cargo.lock"), LOCK).unwrap();
+        }
+
+        let found = find_lockfiles(tmp.path());
+        assert_eq!(
+            found,
+            vec![
+                tmp.path().join("a/Cargo.lock"),
+                tmp.path().join("b/nested/Cargo.lock"),
+            ]
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use anyhow::{Context, Result};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::collections::{HashMap, HashSet};
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 
@@ -15,16 +17,116 @@ pub struct TraceResult {
     pub used_artifacts: HashSet<PathBuf>,
     /// Reverse dep map: artifact path → set of crate names that reference it
     pub used_by: HashMap<PathBuf, HashSet<String>>,
+    /// Wall-clock time spent running the traced command(s)
+    pub duration: Duration,
+    /// Artifacts referenced outside the target and build dirs, e.g. because
+    /// the command passed its own `--target-dir`
+    pub unrooted: HashSet<PathBuf>,
+}
+
+impl TraceResult {
+    /// Fold another trace (e.g. of a second build command) into this one.
+    pub fn merge_from(&mut self, other: TraceResult) {
+        self.used_artifacts.extend(other.used_artifacts);
+        self.unrooted.extend(other.unrooted);
+        self.duration += other.duration;
+        for (path, users) in other.used_by {
+            self.used_by.entry(path).or_default().extend(users);
+        }
+    }
 }
 
 /// Parser for cargo build trace output
 pub struct TraceParser {
-    target_dir: PathBuf,
+    /// The target dir, and a separate build-dir (`build.build-dir`) if any
+    roots: Vec<PathBuf>,
+    /// Where to copy the raw stderr of traced commands, for `parse_log`
+    saved_log: Option<PathBuf>,
+}
+
+/// What a stderr line of a traced build turned out to be.
+#[derive(Debug, PartialEq)]
+enum TraceLine {
+    /// References an artifact; `new` when it was not seen before
+    Artifact { new: bool },
+    /// Shown to the user
+    Output,
+    /// CARGO_LOG noise or a repeated blank line
+    Hidden,
+}
+
+/// Line-by-line state shared by a live trace and a replayed log.
+#[derive(Default)]
+struct LineCollector {
+    result: TraceResult,
+    /// Bytes of the distinct artifacts seen so far
+    total_size: u64,
+    noise: NoiseState,
+    last_line_was_blank: bool,
+}
+
+impl LineCollector {
+    fn feed(&mut self, parser: &TraceParser, line: &str) -> TraceLine {
+        if let Some((path, target)) = parser.extract_artifact_and_target(line) {
+            self.noise = NoiseState::default();
+            let new = self.result.used_artifacts.insert(path.clone());
+            if new && let Ok(meta) = std::fs::metadata(&path) {
+                self.total_size += meta.len();
+            }
+            if let Some(t) = target {
+                self.result.used_by.entry(path).or_default().insert(t);
+            }
+            return TraceLine::Artifact { new };
+        }
+        if let Some((path, target)) = parser.artifact_in_line(line) {
+            // Outside the expected roots; the caller decides what to make of it
+            self.noise = NoiseState::default();
+            if let Some(t) = target {
+                self.result
+                    .used_by
+                    .entry(path.clone())
+                    .or_default()
+                    .insert(t);
+            }
+            self.result.unrooted.insert(path);
+            return TraceLine::Hidden;
+        }
+        if is_cargo_log_noise(line, &mut self.noise) {
+            return TraceLine::Hidden;
+        }
+        let is_blank = line.trim().is_empty();
+        let repeated = is_blank && self.last_line_was_blank;
+        self.last_line_was_blank = is_blank;
+        if repeated {
+            TraceLine::Hidden
+        } else {
+            TraceLine::Output
+        }
+    }
 }
 
 impl TraceParser {
     pub fn new(target_dir: PathBuf) -> Self {
-        Self { target_dir }
+        Self {
+            roots: vec![target_dir],
+            saved_log: None,
+        }
+    }
+
+    /// Also accept artifacts under `build_dir`, where cargo puts intermediate
+    /// artifacts when `build.build-dir` is set.
+    pub fn with_build_dir(mut self, build_dir: PathBuf) -> Self {
+        if !self.roots.contains(&build_dir) {
+            self.roots.push(build_dir);
+        }
+        self
+    }
+
+    /// Also write the raw stderr of traced commands to `path`, which
+    /// `parse_log` can replay later.
+    pub fn save_log_to(mut self, path: Option<PathBuf>) -> Self {
+        self.saved_log = path;
+        self
     }
 
     /// Run `cmd_str` via `sh -c` with CARGO_LOG trace enabled and collect
@@ -32,6 +134,7 @@ impl TraceParser {
     /// Spaces and shell quoting work exactly as they would in the shell.
     pub async fn trace(&self, project_dir: &Path, cmd_str: &str) -> Result<TraceResult> {
         println!("🔍 Tracing with command: {cmd_str}...");
+        let started = Instant::now();
 
         let mut cmd = Command::new("sh");
         cmd.arg("-c").arg(cmd_str);
@@ -48,7 +151,6 @@ impl TraceParser {
         let stdout = child.stdout.take().context("Failed to capture stdout")?;
         let stderr = child.stderr.take().context("Failed to capture stderr")?;
 
-        let mut result = TraceResult::default();
         let mut stdout_reader = BufReader::new(stdout).lines();
         let mut stderr_reader = BufReader::new(stderr).lines();
 
@@ -63,9 +165,16 @@ impl TraceParser {
         spinner.set_message("scanning…");
         spinner.enable_steady_tick(std::time::Duration::from_millis(80));
 
-        let mut total_size: u64 = 0;
-        let mut suppress_remaining: u8 = 0;
-        let mut last_line_was_blank = false;
+        let mut saved_log = self
+            .saved_log
+            .as_ref()
+            .map(|path| {
+                std::fs::File::create(path)
+                    .map(std::io::BufWriter::new)
+                    .with_context(|| format!("creating trace log {}", path.display()))
+            })
+            .transpose()?;
+        let mut lines = LineCollector::default();
 
         loop {
             tokio::select! {
@@ -73,41 +182,35 @@ impl TraceParser {
                     if let Some(line) = stdout_line? { spinner.println(&line); }
                 }
                 stderr_line = stderr_reader.next_line() => {
-                    match stderr_line? {
-                        Some(line) => {
-                            if let Some((path, target)) = self.extract_artifact_and_target(&line) {
-                                suppress_remaining = 0;
-                                if result.used_artifacts.insert(path.clone()) {
-                                    if let Ok(meta) = std::fs::metadata(&path) {
-                                        total_size += meta.len();
-                                    }
-                                    spinner.set_message(format!(
-                                        "{} artifacts  •  {}",
-                                        result.used_artifacts.len(),
-                                        format_bytes(total_size),
-                                    ));
-                                }
-                                if let Some(t) = target {
-                                    result.used_by.entry(path).or_default().insert(t);
-                                }
-                            } else if is_cargo_log_noise(&line, &mut suppress_remaining) {
-                                // Swallow CARGO_LOG trace noise silently
-                            } else {
-                                // Real cargo output (Compiling, warnings, errors, …)
-                                let is_blank = line.trim().is_empty();
-                                if !(is_blank && last_line_was_blank) {
-                                    spinner.println(&line);
-                                }
-                                last_line_was_blank = is_blank;
-                            }
-                        }
-                        None => break,
+                    let Some(line) = stderr_line? else { break };
+                    if let Some(log) = &mut saved_log {
+                        writeln!(log, "{line}").context("writing trace log")?;
+                    }
+                    match lines.feed(self, &line) {
+                        TraceLine::Artifact { new: true } => spinner.set_message(format!(
+                            "{} artifacts  •  {}",
+                            lines.result.used_artifacts.len(),
+                            format_bytes(lines.total_size),
+                        )),
+                        // Real cargo output (Compiling, warnings, errors, …)
+                        TraceLine::Output => spinner.println(&line),
+                        // CARGO_LOG trace noise and repeated blank lines
+                        TraceLine::Artifact { new: false } | TraceLine::Hidden => {}
                     }
                 }
             }
         }
+        if let Some(log) = &mut saved_log {
+            log.flush().context("writing trace log")?;
+        }
+        let LineCollector {
+            mut result,
+            total_size,
+            ..
+        } = lines;
 
         let status = child.wait().await?;
+        result.duration = started.elapsed();
 
         spinner.finish_and_clear();
 
@@ -130,6 +233,24 @@ impl TraceParser {
         Ok(result)
     }
 
+    /// Collect the artifacts referenced in a recorded trace: the stderr of a
+    /// build run with `CARGO_LOG=cargo::core::compiler::fingerprint=trace`.
+    pub fn parse_log(&self, log_path: &Path) -> Result<TraceResult> {
+        let text = std::fs::read_to_string(log_path)
+            .with_context(|| format!("reading trace log {}", log_path.display()))?;
+        let mut lines = LineCollector::default();
+        for line in text.lines() {
+            lines.feed(self, line);
+        }
+        let result = lines.result;
+        println!(
+            "📼 Loaded \x1b[1;36m{}\x1b[0m traced artifacts from {}",
+            result.used_artifacts.len(),
+            log_path.display()
+        );
+        Ok(result)
+    }
+
     /// Extract an artifact path (and the crate that references it) from a trace line.
     ///
     /// Returns `Some((path, Option<target_crate_name>))`.
@@ -137,39 +258,68 @@ impl TraceParser {
     /// giving us the crate that was being fingerprint-checked (i.e. the one that
     /// depends on the artifact).
     fn extract_artifact_and_target(&self, line: &str) -> Option<(PathBuf, Option<String>)> {
-        if !line.contains("mtime") {
-            return None;
-        }
-
-        // Lines look like:  max output mtime for "…" is "/path/to/libfoo.rlib" …
-        let parts: Vec<&str> = line.split('"').collect();
-        if parts.len() < 2 {
-            return None;
-        }
+        find_artifact(line, |p| self.roots.iter().any(|root| p.starts_with(root)))
+    }
 
-        let path = parts.iter().rev().find_map(|part| {
-            let p = PathBuf::from(part);
-            p.extension()
-                .map(|e| e.to_string_lossy().into_owned())
-                .filter(|e| matches!(e.as_str(), "rlib" | "rmeta" | "so" | "dylib" | "dll"))
-                .filter(|_| p.starts_with(&self.target_dir))
-                .map(|_| p)
-        })?;
+    /// Like `extract_artifact_and_target`, wherever the artifact is.
+    fn artifact_in_line(&self, line: &str) -> Option<(PathBuf, Option<String>)> {
+        find_artifact(line, |_| true)
+    }
+}
 
-        // Parse target="crate_name" from the tracing span prefix
-        let target = line.find("target=\"").and_then(|i| {
-            let rest = &line[i + 8..];
-            rest.find('"').map(|j| rest[..j].to_string())
-        });
+/// The last artifact path in a trace line that `accept` takes, and the crate
+/// that references it.
+fn find_artifact(line: &str, accept: impl Fn(&Path) -> bool) -> Option<(PathBuf, Option<String>)> {
+    if !line.contains("mtime") {
+        return None;
+    }
 
-        Some((path, target))
+    // Lines look like:  max output mtime for "…" is "/path/to/libfoo.rlib" …
+    let parts: Vec<&str> = line.split('"').collect();
+    if parts.len() < 2 {
+        return None;
     }
+
+    let path = parts.iter().rev().find_map(|part| {
+        let p = PathBuf::from(part);
+        p.extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .filter(|e| matches!(e.as_str(), "rlib" | "rmeta" | "so" | "dylib" | "dll"))
+            .filter(|_| accept(&p))
+            .map(|_| p)
+    })?;
+
+    // Parse target="crate_name" from the tracing span prefix
+    let target = line.find("target=\"").and_then(|i| {
+        let rest = &line[i + 8..];
+        rest.find('"').map(|j| rest[..j].to_string())
+    });
+
+    Some((path, target))
+}
+
+/// What `is_cargo_log_noise` remembers between lines.
+#[derive(Debug, Default)]
+struct NoiseState {
+    /// Non-empty lines still to hide after a `Caused by:` header
+    suppress_remaining: u8,
+    /// Inside a `Stack backtrace:` block
+    in_backtrace: bool,
+}
+
+/// A frame of a backtrace: `  3: cargo::…` or its `      at /rustc/…` location.
+fn is_backtrace_frame(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("at ")
+        || line
+            .split_once(": ")
+            .is_some_and(|(n, _)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
 }
 
-/// Returns `true` (and updates `suppress_remaining`) when a stderr line is
-/// CARGO_LOG trace noise that should be hidden from the user.
+/// Returns `true` (and updates `state`) when a stderr line is CARGO_LOG trace
+/// noise that should be hidden from the user.
 ///
-/// Three categories of noise from `CARGO_LOG=…=trace`:
+/// Four categories of noise from `CARGO_LOG=…=trace`:
 ///   1. Structured log entries — always contain `cargo::` (the module path).
 ///   2. `Caused by:` — anyhow error-chain header when cargo can't stat a
 ///      not-yet-built artifact during fingerprint checks.
@@ -177,10 +327,19 @@ impl TraceParser {
 ///      We suppress exactly `suppress_remaining` non-empty lines after the
 ///      header (count-based, not indentation-based, so `   Compiling …`
 ///      lines that also start with spaces are never swallowed).
-fn is_cargo_log_noise(line: &str, suppress_remaining: &mut u8) -> bool {
+///   4. `Stack backtrace:` and its frames, which the same errors carry when
+///      `RUST_BACKTRACE` is set. The block ends at the first non-frame line.
+fn is_cargo_log_noise(line: &str, state: &mut NoiseState) -> bool {
+    if state.in_backtrace {
+        if is_backtrace_frame(line) {
+            return true;
+        }
+        state.in_backtrace = false;
+    }
+
     // Consume a suppressed body line (one line per "Caused by:" header)
-    if *suppress_remaining > 0 && !line.trim().is_empty() {
-        *suppress_remaining -= 1;
+    if state.suppress_remaining > 0 && !line.trim().is_empty() {
+        state.suppress_remaining -= 1;
         return true;
     }
 
@@ -195,7 +354,12 @@ fn is_cargo_log_noise(line: &str, suppress_remaining: &mut u8) -> bool {
 
     // anyhow error-chain header — suppress the next non-empty body line
     if line.trim_start().starts_with("Caused by:") {
-        *suppress_remaining = 1;
+        state.suppress_remaining = 1;
+        return true;
+    }
+
+    if line.trim() == "Stack backtrace:" {
+        state.in_backtrace = true;
         return true;
     }
 
@@ -233,5 +397,91 @@ mod tests {
 
         let line = r#"max output mtime is "/other/path/libfoo-abc123.rlib" 123s"#;
         assert_eq!(parser.extract_artifact_and_target(line), None);
+
+        // A separate build-dir, here in the per-unit layout
+        let line = r#"max dep mtime for "l" is "/cache/debug/build/l/7dca/out/libl-7dca.rlib" 1s"#;
+        assert_eq!(parser.extract_artifact_and_target(line), None);
+        let parser = parser.with_build_dir(PathBuf::from("/cache"));
+        assert_eq!(
+            parser.extract_artifact_and_target(line).map(|(p, _)| p),
+            Some(PathBuf::from(
+                "/cache/debug/build/l/7dca/out/libl-7dca.rlib"
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_log_collects_recorded_artifacts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log = tmp.path().join("build.log");
+        std::fs::write(
+            &log,
+            concat!(
+                "   Compiling foo v0.1.0\n",
+                r#"max dep mtime for "bar" is "/project/target/debug/deps/libbar-xyz789.rmeta" 456s"#,
+                "\n",
+                r#"max output mtime for "foo" is "/project/target/debug/deps/libfoo-abc123.rlib" 123s"#,
+                "\n",
+                r#"max dep mtime for "foo" is "/tmp/other/debug/deps/libqux-1.rlib" 7s"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+
+        let parser = TraceParser::new(PathBuf::from("/project/target"));
+        let mut result = parser.parse_log(&log).unwrap();
+        assert_eq!(result.used_artifacts.len(), 2);
+        // Written by a build with its own `--target-dir`
+        assert_eq!(
+            result.unrooted,
+            HashSet::from([PathBuf::from("/tmp/other/debug/deps/libqux-1.rlib")])
+        );
+
+        let mut other = TraceResult::default();
+        other
+            .used_artifacts
+            .insert(PathBuf::from("/project/target/debug/deps/libbaz-1.rlib"));
+        result.merge_from(other);
+        assert_eq!(result.used_artifacts.len(), 3);
+    }
+
+    /// A real `cargo build` log (cargo 1.95, `RUST_BACKTRACE=1`) saved with
+    /// `--save-trace-log`: only cargo's own progress lines may be shown.
+    #[test]
+    fn captured_log_yields_artifacts_and_hides_noise() {
+        let parser = TraceParser::new(PathBuf::from("/tmp/demo/target"));
+        let mut lines = LineCollector::default();
+        let shown: Vec<&str> = include_str!("../tests/fixtures/cargo-1.95-trace.log")
+            .lines()
+            .filter(|line| lines.feed(&parser, line) == TraceLine::Output)
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+
+        let rlib = PathBuf::from("/tmp/demo/target/debug/deps/libl-4b4b7ab1c4c8e016.rlib");
+        assert_eq!(lines.result.used_artifacts, HashSet::from([rlib.clone()]));
+        assert_eq!(
+            lines.result.used_by[&rlib],
+            HashSet::from(["demo".to_string()])
+        );
+        assert_eq!(shown.len(), 2, "{shown:#?}");
+        assert!(shown[0].contains("Compiling"));
+        assert!(shown[1].contains("Finished"));
+    }
+
+    #[test]
+    fn backtrace_ends_at_the_first_non_frame_line() {
+        let mut state = NoiseState::default();
+        let hidden: Vec<bool> = [
+            "Stack backtrace:",
+            "   0: cargo_util::paths::read_bytes",
+            "             at /rustc/abc/library/std/src/rt.rs:171:5",
+            "  26: <unknown>",
+            "   Compiling foo v0.1.0",
+            "  1: not a frame once the block ended",
+        ]
+        .into_iter()
+        .map(|line| is_cargo_log_noise(line, &mut state))
+        .collect();
+        assert_eq!(hidden, [true, true, true, true, false, false]);
     }
 }
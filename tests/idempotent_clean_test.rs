@@ -59,6 +59,10 @@ fn run_clean(dir: &Path, build_cmd: &str) -> String {
         .current_dir(dir)
         .args(["--yes", "-c", build_cmd])
         .env_remove("CARGO_TARGET_DIR")
+        .env(
+            "CARGO_CLEAN_ARTIFACT_LEDGER",
+            dir.join("target/ledger.jsonl"),
+        )
         .output()
         .expect("failed to spawn cleaner");
     let stdout = String::from_utf8_lossy(&out.stdout).into_owned();